@@ -30,13 +30,13 @@ fn spider_run() {
     config_logger();
     let future = async move {
         let config = pixiv::config::GLOBAL_CONFIG.clone();
-        let collection = mongodb::Client::with_uri_str(&config.mongo_url)
+        let database = mongodb::Client::with_uri_str(&config.mongo_url)
             .await
             .unwrap()
-            .database("Pixiv")
-            .collection("Illusts");
-        
-        
+            .database("Pixiv");
+        let collection = database.collection("Illusts");
+        spider::account_usage::restore(config.clone(), database.collection("AccountUsage")).await;
+
         let h1 = async_std::task::spawn(spider::artworks_spider::run(
             config.clone(),
             collection.clone(),
@@ -46,9 +46,58 @@ fn spider_run() {
             config.clone(),
             collection.clone(),
         ));
+        let h4 = async_std::task::spawn(spider::ranking_spider::run(
+            config.clone(),
+            collection.clone(),
+        ));
+        let h5 = async_std::task::spawn(spider::account_usage::run(
+            config.clone(),
+            database.collection("AccountUsage"),
+        ));
+        let h6 = async_std::task::spawn(spider::download_spider::run(
+            config.clone(),
+            collection.clone(),
+        ));
+        let h7 = async_std::task::spawn(spider::thumbnail_spider::run(
+            config.clone(),
+            collection.clone(),
+        ));
+        let h8 = async_std::task::spawn(spider::phash_spider::run(
+            config.clone(),
+            collection.clone(),
+        ));
+        let h9 = async_std::task::spawn(spider::novels_spider::run(
+            config.clone(),
+            collection.clone(),
+            database.collection("Novels"),
+        ));
+        let h10 = async_std::task::spawn(spider::series_spider::run(
+            config.clone(),
+            collection.clone(),
+            database.collection("Series"),
+        ));
+        let h11 = async_std::task::spawn(spider::users_spider::run(
+            config.clone(),
+            collection.clone(),
+            database.collection("Users"),
+        ));
+        let h12 = async_std::task::spawn(spider::following_spider::run(
+            config.clone(),
+            collection.clone(),
+            database.collection("Users"),
+        ));
         h1.await;
         h2.await;
         h3.await;
+        h4.await;
+        h5.await;
+        h6.await;
+        h7.await;
+        h8.await;
+        h9.await;
+        h10.await;
+        h11.await;
+        h12.await;
         
     };
 
@@ -57,14 +106,295 @@ fn spider_run() {
     async_std::task::block_on(future);
 }
 
+/// 子命令出错时输出到 stderr 并以状态码 1 退出, 方便脚本判断是否成功
+fn exit_with_error(message: impl std::fmt::Display) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1)
+}
+
+/// 离线把动图压缩包转换为 GIF/APNG/WebP, 格式由输出文件扩展名决定
+///
+/// `frames` 可以是 ugoira_meta 的 JSON (含 `frames` 字段) 或帧数组, 不提供时按文件名顺序每帧 100 毫秒
+fn ugoira_run(args: &[String]) {
+    if args.len() < 2 {
+        exit_with_error("usage : ugoira <zip> <output.gif|output.png|output.webp> [frames.json]");
+    }
+    let format = match pixiv::base::AnimationFormat::from_path(&args[1]) {
+        Some(x) => x,
+        None => exit_with_error(format!("不支持的输出格式: {}", args[1])),
+    };
+    let zip_data = match std::fs::read(&args[0]) {
+        Ok(x) => x,
+        Err(e) => exit_with_error(format!("读取 {} 失败: {}", args[0], e)),
+    };
+    let frames = match args.get(2) {
+        Some(path) => {
+            let value = match std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|x| {
+                    serde_json::from_str::<serde_json::Value>(&x).map_err(|e| e.to_string())
+                }) {
+                Ok(x) => x,
+                Err(e) => exit_with_error(format!("读取 {} 失败: {}", path, e)),
+            };
+            let frames = value.get("frames").unwrap_or(&value).clone();
+            match serde_json::from_value::<Vec<pixiv::base::UgoiraFrame>>(frames) {
+                Ok(x) => x,
+                Err(e) => exit_with_error(format!("帧信息格式错误 {}: {}", path, e)),
+            }
+        }
+        None => match pixiv::base::frames_from_zip(&zip_data, 100) {
+            Ok(x) => x,
+            Err(e) => exit_with_error(e),
+        },
+    };
+    match pixiv::base::convert_ugoira(&zip_data, &frames, format) {
+        Ok(x) => match std::fs::write(&args[1], &x) {
+            Ok(_) => println!("{} 帧 -> {} ({} 字节)", frames.len(), args[1], x.len()),
+            Err(e) => exit_with_error(format!("写入 {} 失败: {}", args[1], e)),
+        },
+        Err(e) => exit_with_error(e),
+    }
+}
+
+/// 按感知哈希列出疑似重复上传的作品, 每组一行: 作品id_p页码
+///
+/// `max_distance` 为汉明距离阈值, 默认 6
+fn duplicates_run(args: &[String]) {
+    use futures::StreamExt;
+    let max_distance = match args.first() {
+        Some(x) => match x.parse::<u32>() {
+            Ok(x) => x,
+            Err(_) => exit_with_error("usage : duplicates [max_distance]"),
+        },
+        None => 6,
+    };
+    let future = async move {
+        let config = pixiv::config::GLOBAL_CONFIG.clone();
+        let collection = match mongodb::Client::with_uri_str(&config.mongo_url).await {
+            Ok(x) => x.database("Pixiv").collection("Illusts"),
+            Err(e) => exit_with_error(format!("连接数据库失败: {}", e)),
+        };
+        let mut cursor = match collection
+            .find(
+                mongodb::bson::doc! {"files.phash" : {"$exists" : 1}},
+                mongodb::options::FindOptions::builder()
+                    .projection(mongodb::bson::doc! {"id" : 1, "files" : 1})
+                    .build(),
+            )
+            .await
+        {
+            Ok(x) => x,
+            Err(e) => exit_with_error(format!("查询作品失败: {}", e)),
+        };
+        let mut pages: Vec<(i64, i32)> = Vec::new();
+        let mut hashes: Vec<u64> = Vec::new();
+        while let Some(document) = cursor.next().await {
+            let document = match document {
+                Ok(x) => x,
+                Err(e) => exit_with_error(format!("查询作品失败: {}", e)),
+            };
+            let artwork_id = match document.get("id") {
+                Some(mongodb::bson::Bson::Int64(x)) => *x,
+                Some(mongodb::bson::Bson::Int32(x)) => *x as i64,
+                _ => continue,
+            };
+            let files = match document.get_array("files") {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            for (index, file) in files.iter().enumerate() {
+                let file = match file.as_document() {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let hash = match file
+                    .get_str("phash")
+                    .ok()
+                    .and_then(pixiv::base::hash_from_hex)
+                {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let page = file.get_i32("page").unwrap_or(index as i32);
+                pages.push((artwork_id, page));
+                hashes.push(hash);
+            }
+        }
+        let mut count = 0;
+        for cluster in pixiv::base::cluster_hashes(&hashes, max_distance) {
+            // 同一作品内的相似页不算重复上传
+            let first = pages[cluster[0]].0;
+            if cluster.iter().all(|&x| pages[x].0 == first) {
+                continue;
+            }
+            let line = cluster
+                .iter()
+                .map(|&x| format!("{}_p{}", pages[x].0, pages[x].1))
+                .collect::<Vec<_>>()
+                .join(" ");
+            println!("{}", line);
+            count += 1;
+        }
+        println!(
+            "共 {} 张图片, {} 组疑似重复 (汉明距离 <= {})",
+            hashes.len(),
+            count,
+            max_distance
+        );
+    };
+    async_std::task::block_on(future);
+}
+
+/// 把已下载的漫画/多页作品打包成 `{output_dir}/{作品id}.cbz`, 已存在的跳过
+///
+/// 不指定作品id时导出数据库中所有已下载的漫画和多页作品
+fn cbz_run(args: &[String]) {
+    use futures::StreamExt;
+    if args.is_empty() {
+        exit_with_error("usage : cbz <output_dir> [artwork_id ...]");
+    }
+    let output_dir = std::path::PathBuf::from(&args[0]);
+    let mut ids = Vec::new();
+    for x in &args[1..] {
+        match x.parse::<i64>() {
+            Ok(x) => ids.push(x),
+            Err(_) => exit_with_error(format!("作品id错误: {}", x)),
+        }
+    }
+    let future = async move {
+        let config = pixiv::config::GLOBAL_CONFIG.clone();
+        let collection = match mongodb::Client::with_uri_str(&config.mongo_url).await {
+            Ok(x) => x.database("Pixiv").collection("Illusts"),
+            Err(e) => exit_with_error(format!("连接数据库失败: {}", e)),
+        };
+        let filter = if ids.is_empty() {
+            mongodb::bson::doc! {
+                "files" : {"$exists" : 1},
+                "$or" : [{"type" : "manga"}, {"page_count" : {"$gt" : 1}}],
+            }
+        } else {
+            mongodb::bson::doc! {"id" : {"$in" : ids}, "files" : {"$exists" : 1}}
+        };
+        let mut cursor = match collection.find(filter, None).await {
+            Ok(x) => x,
+            Err(e) => exit_with_error(format!("查询作品失败: {}", e)),
+        };
+        let mut count = 0;
+        let mut failed = 0;
+        while let Some(document) = cursor.next().await {
+            let document = match document {
+                Ok(x) => x,
+                Err(e) => exit_with_error(format!("查询作品失败: {}", e)),
+            };
+            let artwork = match mongodb::bson::from_document::<pixiv::base::Artwork>(document) {
+                Ok(x) => x,
+                Err(e) => {
+                    eprintln!("{:?}", e);
+                    failed += 1;
+                    continue;
+                }
+            };
+            let output = output_dir.join(format!("{}.cbz", artwork.artwork_id));
+            if output.exists() {
+                continue;
+            }
+            match pixiv::base::export_cbz(&artwork, &output) {
+                Ok(_) => {
+                    println!("{} ({} 页)", output.display(), artwork.files.len());
+                    count += 1;
+                }
+                Err(e) => {
+                    eprintln!("{} 导出失败: {}", artwork.artwork_id, e);
+                    failed += 1;
+                }
+            }
+        }
+        println!("共导出 {} 个作品", count);
+        if failed > 0 {
+            exit_with_error(format!("{} 个作品导出失败", failed));
+        }
+    };
+    async_std::task::block_on(future);
+}
+
+/// 从数据库导出小说为 EPUB, 不访问网络
+///
+/// `series:<系列id>` 导出整个系列, 按系列中的顺序排列; 封面需要指定本地图片文件, 不指定时不生成封面
+fn epub_run(args: &[String]) {
+    use futures::StreamExt;
+    if args.len() < 2 {
+        exit_with_error("usage : epub <output.epub> <novel_id | series:series_id> [cover]");
+    }
+    let output = std::path::PathBuf::from(&args[0]);
+    let (filter, is_series) = match args[1].strip_prefix("series:") {
+        Some(x) => match x.parse::<i64>() {
+            Ok(id) => (mongodb::bson::doc! {"series.id" : id}, true),
+            Err(_) => exit_with_error(format!("系列id错误: {}", x)),
+        },
+        None => match args[1].parse::<i64>() {
+            Ok(id) => (mongodb::bson::doc! {"id" : id}, false),
+            Err(_) => exit_with_error(format!("小说id错误: {}", args[1])),
+        },
+    };
+    let cover = args.get(2).map(std::path::PathBuf::from);
+    let future = async move {
+        let config = pixiv::config::GLOBAL_CONFIG.clone();
+        let collection = match mongodb::Client::with_uri_str(&config.mongo_url).await {
+            Ok(x) => x.database("Pixiv").collection("Novels"),
+            Err(e) => exit_with_error(format!("连接数据库失败: {}", e)),
+        };
+        let options = mongodb::options::FindOptions::builder()
+            .sort(mongodb::bson::doc! {"series.order" : 1, "create_date" : 1})
+            .build();
+        let mut cursor = match collection.find(filter, options).await {
+            Ok(x) => x,
+            Err(e) => exit_with_error(format!("查询小说失败: {}", e)),
+        };
+        let mut novels = Vec::new();
+        while let Some(document) = cursor.next().await {
+            let document = match document {
+                Ok(x) => x,
+                Err(e) => exit_with_error(format!("查询小说失败: {}", e)),
+            };
+            // 缺少章节的 EPUB 没有意义, 有文档无法解析时直接退出
+            match mongodb::bson::from_document::<pixiv::base::Novel>(document) {
+                Ok(x) => novels.push(x),
+                Err(e) => exit_with_error(format!("解析小说失败: {:?}", e)),
+            }
+        }
+        let title = if is_series {
+            novels
+                .first()
+                .and_then(|x| x.series.as_ref())
+                .and_then(|x| x.title.clone())
+        } else {
+            None
+        };
+        match pixiv::base::export_epub(&novels, title.as_deref(), cover.as_deref(), &output) {
+            Ok(_) => println!("{} 篇小说 -> {}", novels.len(), output.display()),
+            Err(e) => exit_with_error(e),
+        }
+    };
+    async_std::task::block_on(future);
+}
+
 fn main() {
     let args = std::env::args().skip(1).collect::<Vec<String>>();
     if args.len() <= 0 {
-        println!("usage : spider");
+        println!("usage : spider | ugoira | duplicates | cbz | epub");
         return;
     }
     let subcommand = args.get(0).unwrap();
     if subcommand == "spider" {
         spider_run();
+    } else if subcommand == "ugoira" {
+        ugoira_run(&args[1..]);
+    } else if subcommand == "duplicates" {
+        duplicates_run(&args[1..]);
+    } else if subcommand == "cbz" {
+        cbz_run(&args[1..]);
+    } else if subcommand == "epub" {
+        epub_run(&args[1..]);
     }
 }
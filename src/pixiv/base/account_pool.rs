@@ -0,0 +1,250 @@
+use super::pixiv_client::CookieJar;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AccountConfig {
+    pub name: String,
+    pub cookie: String,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// 每天最多发出的请求数, 不设置则不限
+    #[serde(default)]
+    pub daily_quota: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountUsage {
+    pub name: String,
+    pub date: String,
+    pub requests_today: i64,
+    pub requests_total: i64,
+    pub retired: bool,
+}
+
+struct AccountState {
+    config: AccountConfig,
+    jar: Mutex<CookieJar>,
+    /// 配置中的 Cookie 是否带有登录态
+    session_configured: bool,
+    retired: AtomicBool,
+    used_today: AtomicU64,
+    used_total: AtomicU64,
+    /// 还没有写入数据库的请求数
+    unsaved: AtomicU64,
+}
+
+/// 多个账号组成的账号池, 请求轮流分配给未失效且额度未用完的账号
+pub struct AccountPool {
+    accounts: Vec<AccountState>,
+    cursor: AtomicUsize,
+    /// 当前计数对应的日期 (本地时间, 距 1970-01-01 的天数)
+    day: AtomicI64,
+}
+
+fn today() -> i64 {
+    let now = chrono::Local::now();
+    (now.timestamp() + now.offset().local_minus_utc() as i64) / 86400
+}
+
+impl AccountPool {
+    pub fn new(accounts: Vec<AccountConfig>) -> AccountPool {
+        AccountPool {
+            accounts: accounts
+                .into_iter()
+                .map(|x| {
+                    let jar = CookieJar::parse(&x.cookie);
+                    AccountState {
+                        session_configured: jar.has_session(),
+                        jar: Mutex::new(jar),
+                        config: x,
+                        retired: AtomicBool::new(false),
+                        used_today: AtomicU64::new(0),
+                        used_total: AtomicU64::new(0),
+                        unsaved: AtomicU64::new(0),
+                    }
+                })
+                .collect(),
+            cursor: AtomicUsize::new(0),
+            day: AtomicI64::new(today()),
+        }
+    }
+
+    fn reset_if_new_day(&self) {
+        let day = today();
+        if self.day.swap(day, Ordering::Relaxed) != day {
+            for account in &self.accounts {
+                account.used_today.store(0, Ordering::Relaxed);
+            }
+            info!("新的一天, 账号额度已重置");
+        }
+    }
+
+    /// 选出下一个可用账号并计入一次请求, 没有可用账号时返回 None
+    pub fn acquire(&self) -> Option<usize> {
+        self.reset_if_new_day();
+        let len = self.accounts.len();
+        for _ in 0..len {
+            let index = self.cursor.fetch_add(1, Ordering::Relaxed) % len;
+            let account = &self.accounts[index];
+            if account.retired.load(Ordering::Relaxed) {
+                continue;
+            }
+            let used = account.used_today.fetch_add(1, Ordering::Relaxed);
+            if let Some(quota) = account.config.daily_quota {
+                if used >= quota {
+                    account.used_today.fetch_sub(1, Ordering::Relaxed);
+                    continue;
+                }
+            }
+            account.used_total.fetch_add(1, Ordering::Relaxed);
+            account.unsaved.fetch_add(1, Ordering::Relaxed);
+            return Some(index);
+        }
+        None
+    }
+
+    pub fn name(&self, index: usize) -> &str {
+        &self.accounts[index].config.name
+    }
+
+    pub fn user_agent(&self, index: usize) -> Option<&str> {
+        self.accounts[index].config.user_agent.as_deref()
+    }
+
+    pub fn cookie_header(&self, index: usize) -> Option<String> {
+        self.accounts[index].jar.lock().unwrap().header_value()
+    }
+
+    pub fn update_cookies(&self, index: usize, set_cookie: &str) {
+        self.accounts[index]
+            .jar
+            .lock()
+            .unwrap()
+            .update_from_set_cookie(set_cookie);
+    }
+
+    pub fn has_session(&self, index: usize) -> bool {
+        self.accounts[index].jar.lock().unwrap().has_session()
+    }
+
+    pub fn session_configured(&self, index: usize) -> bool {
+        self.accounts[index].session_configured
+    }
+
+    /// 登录失效的账号不再分配请求
+    pub fn retire(&self, index: usize) {
+        let account = &self.accounts[index];
+        if !account.retired.swap(true, Ordering::Relaxed) {
+            error!("账号 {} 登录已失效, 停止使用", account.config.name);
+        }
+    }
+
+    /// 用数据库中的记录恢复计数, 避免重启后额度从零开始
+    ///
+    /// 只有今天的记录会恢复 `requests_today`, `requests_total` 取所有记录中的最大值
+    pub fn restore_usage(&self, records: &[AccountUsage]) {
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+        for record in records {
+            let account = match self.accounts.iter().find(|x| x.config.name == record.name) {
+                Some(x) => x,
+                None => continue,
+            };
+            if record.date == date {
+                account
+                    .used_today
+                    .fetch_max(record.requests_today.max(0) as u64, Ordering::Relaxed);
+            }
+            account
+                .used_total
+                .fetch_max(record.requests_total.max(0) as u64, Ordering::Relaxed);
+        }
+        for account in &self.accounts {
+            info!(
+                "账号 {} 今日已请求 {} 次",
+                account.config.name,
+                account.used_today.load(Ordering::Relaxed)
+            );
+        }
+    }
+
+    /// 取出每个账号自上次调用以来新增的请求数, 与 `usage()` 的顺序一致
+    pub fn take_unsaved(&self) -> Vec<u64> {
+        self.accounts
+            .iter()
+            .map(|x| x.unsaved.swap(0, Ordering::Relaxed))
+            .collect()
+    }
+
+    pub fn usage(&self) -> Vec<AccountUsage> {
+        self.reset_if_new_day();
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+        self.accounts
+            .iter()
+            .map(|x| AccountUsage {
+                name: x.config.name.clone(),
+                date: date.clone(),
+                requests_today: x.used_today.load(Ordering::Relaxed) as i64,
+                requests_total: x.used_total.load(Ordering::Relaxed) as i64,
+                retired: x.retired.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(name: &str, quota: Option<u64>) -> AccountConfig {
+        AccountConfig {
+            name: name.into(),
+            cookie: "PHPSESSID=1".into(),
+            user_agent: None,
+            daily_quota: quota,
+        }
+    }
+
+    #[test]
+    fn restored_usage_counts_against_quota() {
+        let pool = AccountPool::new(vec![account("a", Some(3)), account("b", None)]);
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        pool.restore_usage(&[
+            AccountUsage {
+                name: "a".into(),
+                date: today,
+                requests_today: 2,
+                requests_total: 10,
+                retired: false,
+            },
+            AccountUsage {
+                name: "b".into(),
+                date: "2000-01-01".into(),
+                requests_today: 50,
+                requests_total: 50,
+                retired: false,
+            },
+        ]);
+        let usage = pool.usage();
+        assert_eq!((usage[0].requests_today, usage[0].requests_total), (2, 10));
+        assert_eq!((usage[1].requests_today, usage[1].requests_total), (0, 50));
+        assert_eq!(pool.acquire(), Some(0));
+        assert_eq!(pool.acquire(), Some(1));
+        // a 的额度已用完, 只剩 b
+        assert_eq!(pool.acquire(), Some(1));
+        assert_eq!(pool.take_unsaved(), vec![1, 2]);
+        assert_eq!(pool.take_unsaved(), vec![0, 0]);
+    }
+
+    #[test]
+    fn retired_accounts_are_skipped() {
+        let pool = AccountPool::new(vec![account("a", None), account("b", None)]);
+        pool.retire(0);
+        assert_eq!(pool.acquire(), Some(1));
+        assert_eq!(pool.acquire(), Some(1));
+        pool.retire(1);
+        assert_eq!(pool.acquire(), None);
+    }
+}
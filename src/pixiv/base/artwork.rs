@@ -25,7 +25,7 @@ pub struct Artwork {
         rename(serialize = "type", deserialize = "type"),
         skip_serializing_if = "Option::is_none"
     )]
-    pub artwork_type: Option<String>,
+    pub artwork_type: Option<ArtworkType>,
     #[serde(
         rename(serialize = "height", deserialize = "height"),
         skip_serializing_if = "Option::is_none"
@@ -66,6 +66,18 @@ pub struct Artwork {
         skip_serializing_if = "Option::is_none"
     )]
     pub image_urls: Option<PixivImageUrls>,
+    #[serde(
+        rename(serialize = "page_count", deserialize = "page_count"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub page_count: Option<i32>,
+    /// 多页作品每一页的图片地址, 由 `PixivClient::load_pages` 填充
+    #[serde(
+        rename(serialize = "pages", deserialize = "pages"),
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub pages: Vec<PixivImageUrls>,
     #[serde(
         rename(serialize = "user", deserialize = "user"),
         skip_serializing_if = "Option::is_none"
@@ -76,6 +88,124 @@ pub struct Artwork {
         skip_serializing_if = "Option::is_none"
     )]
     pub tags: Option<Vec<PixivTag>>,
+    #[serde(
+        rename(serialize = "rankings", deserialize = "rankings"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub rankings: Option<Vec<PixivRanking>>,
+    #[serde(
+        rename(serialize = "ugoira", deserialize = "ugoira"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ugoira: Option<UgoiraMeta>,
+    /// 所属的漫画/插画系列
+    #[serde(
+        rename(serialize = "series", deserialize = "series"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub series: Option<PixivSeriesNav>,
+    /// 已下载到本地的原图, 由 download_spider 填充
+    #[serde(
+        rename(serialize = "files", deserialize = "files"),
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub files: Vec<PixivFile>,
+}
+
+/// 本地保存的一张原图
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PixivFile {
+    #[serde(rename(serialize = "page", deserialize = "page"))]
+    pub page: i32,
+    #[serde(rename(serialize = "url", deserialize = "url"))]
+    pub url: String,
+    #[serde(rename(serialize = "path", deserialize = "path"))]
+    pub path: String,
+    #[serde(rename(serialize = "size", deserialize = "size"))]
+    pub size: i64,
+    #[serde(rename(serialize = "sha256", deserialize = "sha256"))]
+    pub sha256: String,
+    #[serde(
+        rename(serialize = "thumbnails", deserialize = "thumbnails"),
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub thumbnails: Vec<PixivThumbnail>,
+    /// 感知哈希 (dHash), 16 位十六进制
+    #[serde(
+        rename(serialize = "phash", deserialize = "phash"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub phash: Option<String>,
+}
+
+/// 原图的缩略图, `long_edge` 为配置的长边尺寸, `width`/`height` 为实际尺寸
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PixivThumbnail {
+    #[serde(rename(serialize = "long_edge", deserialize = "long_edge"))]
+    pub long_edge: i32,
+    #[serde(rename(serialize = "path", deserialize = "path"))]
+    pub path: String,
+    #[serde(rename(serialize = "width", deserialize = "width"))]
+    pub width: i32,
+    #[serde(rename(serialize = "height", deserialize = "height"))]
+    pub height: i32,
+}
+
+/// 对应 preload 中的 `illustType`: 0 插画, 1 漫画, 2 动图
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ArtworkType {
+    #[serde(rename = "illust")]
+    Illust,
+    #[serde(rename = "manga")]
+    Manga,
+    #[serde(rename = "ugoira")]
+    Ugoira,
+    #[serde(rename = "unknow")]
+    Unknown,
+}
+
+impl From<i64> for ArtworkType {
+    fn from(v: i64) -> ArtworkType {
+        match v {
+            0 => ArtworkType::Illust,
+            1 => ArtworkType::Manga,
+            2 => ArtworkType::Ugoira,
+            _ => ArtworkType::Unknown,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UgoiraFrame {
+    #[serde(rename(serialize = "file", deserialize = "file"))]
+    pub file: String,
+    /// 该帧的显示时间, 单位毫秒
+    #[serde(rename(serialize = "delay", deserialize = "delay"))]
+    pub delay: i32,
+}
+
+/// `/ajax/illust/{id}/ugoira_meta` 返回的动图信息, 帧图片打包在 zip 中
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UgoiraMeta {
+    #[serde(
+        rename(serialize = "src", deserialize = "src"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub src: Option<String>,
+    #[serde(
+        rename(serialize = "original_src", deserialize = "original_src"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub original_src: Option<String>,
+    #[serde(
+        rename(serialize = "mime_type", deserialize = "mime_type"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mime_type: Option<String>,
+    #[serde(rename(serialize = "frames", deserialize = "frames"))]
+    pub frames: Vec<UgoiraFrame>,
 }
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PixivImageUrls {
@@ -124,6 +254,36 @@ pub struct PixivTag {
     pub trans: Option<String>,
 }
 
+/// 作品在某一期排行榜中的名次
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PixivRanking {
+    #[serde(rename(serialize = "mode", deserialize = "mode"))]
+    pub mode: String,
+    #[serde(rename(serialize = "content", deserialize = "content"))]
+    pub content: String,
+    #[serde(rename(serialize = "date", deserialize = "date"))]
+    pub date: String,
+    #[serde(rename(serialize = "rank", deserialize = "rank"))]
+    pub rank: i32,
+}
+
+/// preload 中的 `seriesNavData`: 作品所属系列及在系列中的序号
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PixivSeriesNav {
+    #[serde(rename(serialize = "id", deserialize = "id"))]
+    pub series_id: i64,
+    #[serde(
+        rename(serialize = "title", deserialize = "title"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub title: Option<String>,
+    #[serde(
+        rename(serialize = "order", deserialize = "order"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub order: Option<i32>,
+}
+
 use std::convert::TryFrom;
 
 macro_rules! JSON_GET {
@@ -166,13 +326,13 @@ impl TryFrom<&serde_json::Value> for Artwork {
         let total_bookmarks = JSON_GET!(value, "bookmarkCount", as_i64, |x| x as i32);
         let total_view = JSON_GET!(value, "viewCount", as_i64, |x| x as i32);
         let width = JSON_GET!(value, "width", as_i64, |x| x as i32);
-        let artwork_type = JSON_GET!(value, "illustType", as_i64, |x| {
-            match x {
-                0 => "illust".to_string(),
-                1 => "manga".to_string(),
-                _ => "unknow".to_string(),
-            }
-        });
+        let page_count = JSON_GET!(value, "pageCount", as_i64, |x| x as i32);
+        let artwork_type = JSON_GET!(value, "illustType", as_i64, ArtworkType::from);
+        // 不属于系列时 seriesNavData 为 null
+        let series = match value.get("seriesNavData") {
+            Some(x) if !x.is_null() => PixivSeriesNav::try_from(x).ok(),
+            _ => None,
+        };
         let user = match PixivUser::try_from(value) {
             Ok(x) => Some(x),
             Err(_) => None,
@@ -219,10 +379,16 @@ impl TryFrom<&serde_json::Value> for Artwork {
             total_view: total_view,
             width: width,
             image_urls: images_urls,
+            page_count: page_count,
+            pages: Vec::new(),
             user: user,
             tags: tags,
             last_update_time: None,
             artwork_type: artwork_type,
+            rankings: None,
+            ugoira: None,
+            series,
+            files: Vec::new(),
         })
     }
 }
@@ -238,6 +404,40 @@ impl TryFrom<&serde_json::Value> for PixivImageUrls {
     }
 }
 
+impl PixivImageUrls {
+    /// `/ajax/illust/{id}/pages` 中每一页的 `urls`, 缩略图字段为 `thumb_mini`
+    pub fn try_from_page(value: &serde_json::Value) -> Result<Self, FromError> {
+        Ok(PixivImageUrls {
+            medium: JSON_GET!(value, "regular", as_str, |x| x.to_string()),
+            square_medium: JSON_GET!(value, "thumb_mini", as_str, |x| x.to_string()),
+            large: JSON_GET!(value, "original", as_str, |x| x.to_string()),
+        })
+    }
+}
+
+impl TryFrom<&serde_json::Value> for UgoiraMeta {
+    type Error = FromError;
+    fn try_from(value: &serde_json::Value) -> Result<Self, Self::Error> {
+        let mut frames = Vec::new();
+        if let Some(array) = JSON_GET!(value, "frames", as_array) {
+            for item in array {
+                let file = match JSON_GET!(item, "file", as_str, |x| x.to_string()) {
+                    Some(x) => x,
+                    None => return Err(FromError("frame.file必须存在".to_string())),
+                };
+                let delay = JSON_GET!(item, "delay", as_i64, |x| x as i32).unwrap_or(100);
+                frames.push(UgoiraFrame { file, delay });
+            }
+        }
+        Ok(UgoiraMeta {
+            src: JSON_GET!(value, "src", as_str, |x| x.to_string()),
+            original_src: JSON_GET!(value, "originalSrc", as_str, |x| x.to_string()),
+            mime_type: JSON_GET!(value, "mime_type", as_str, |x| x.to_string()),
+            frames,
+        })
+    }
+}
+
 impl TryFrom<&serde_json::Value> for PixivUser {
     type Error = FromError;
     fn try_from(value: &serde_json::Value) -> Result<Self, Self::Error> {
@@ -283,3 +483,201 @@ impl TryFrom<&serde_json::Value> for PixivTag {
         })
     }
 }
+
+impl TryFrom<&serde_json::Value> for PixivSeriesNav {
+    type Error = FromError;
+    fn try_from(value: &serde_json::Value) -> Result<Self, Self::Error> {
+        // seriesId 有时是字符串有时是数字
+        let series_id = match value.get("seriesId") {
+            Some(serde_json::Value::String(x)) => x.parse::<i64>().ok(),
+            Some(x) => x.as_i64(),
+            None => None,
+        };
+        let series_id = match series_id {
+            Some(x) => x,
+            None => return Err(FromError("seriesId必须存在".to_string())),
+        };
+        Ok(PixivSeriesNav {
+            series_id,
+            title: JSON_GET!(value, "title", as_str, |x| x.to_string()),
+            order: JSON_GET!(value, "order", as_i64, |x| x as i32),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str =
+        "https://i.pximg.net/img-original/img/2020/12/01/00/00/00/84000001_p0.png";
+    const REGULAR: &str =
+        "https://i.pximg.net/img-master/img/2020/12/01/00/00/00/84000001_p0_master1200.jpg";
+
+    #[test]
+    fn image_urls_from_preload_and_pages() {
+        let urls = serde_json::json!({
+            "mini": "https://i.pximg.net/c/48x48/img-master/img/2020/12/01/00/00/00/84000001_p0_square1200.jpg",
+            "thumb": "https://i.pximg.net/c/250x250_80_a2/img-master/img/2020/12/01/00/00/00/84000001_p0_square1200.jpg",
+            "small": "https://i.pximg.net/c/540x540_70/img-master/img/2020/12/01/00/00/00/84000001_p0_master1200.jpg",
+            "regular": REGULAR,
+            "original": ORIGINAL,
+        });
+        let x = PixivImageUrls::try_from(&urls).unwrap();
+        assert_eq!(x.large.as_deref(), Some(ORIGINAL));
+        assert_eq!(x.medium.as_deref(), Some(REGULAR));
+        assert!(x.square_medium.unwrap().contains("250x250"));
+
+        // pages 接口的缩略图字段是 thumb_mini
+        let page = serde_json::json!({
+            "thumb_mini": "https://i.pximg.net/c/128x128/img-master/img/2020/12/01/00/00/00/84000001_p1_square1200.jpg",
+            "small": "https://i.pximg.net/c/540x540_70/img-master/img/2020/12/01/00/00/00/84000001_p1_master1200.jpg",
+            "regular": "https://i.pximg.net/img-master/img/2020/12/01/00/00/00/84000001_p1_master1200.jpg",
+            "original": "https://i.pximg.net/img-original/img/2020/12/01/00/00/00/84000001_p1.png",
+        });
+        let x = PixivImageUrls::try_from_page(&page).unwrap();
+        assert!(x.large.unwrap().ends_with("84000001_p1.png"));
+        assert!(x.square_medium.unwrap().contains("128x128"));
+        assert!(PixivImageUrls::try_from(&page)
+            .unwrap()
+            .square_medium
+            .is_none());
+
+        let empty = PixivImageUrls::try_from_page(&serde_json::json!({})).unwrap();
+        assert!(empty.large.is_none());
+        assert!(PixivImageUrls::try_from_page(&serde_json::json!({"original": 1})).is_err());
+    }
+
+    #[test]
+    fn artwork_from_preload() {
+        let value = serde_json::json!({
+            "id": "84000001",
+            "title": "漫画",
+            "illustType": 1,
+            "illustComment": "caption",
+            "createDate": "2020-12-01T00:00:00+00:00",
+            "pageCount": 3,
+            "width": 1200,
+            "height": 1700,
+            "sl": 2,
+            "bookmarkCount": 10,
+            "viewCount": 100,
+            "userId": "11",
+            "userName": "pixiv事務局",
+            "userAccount": "pixiv",
+            "urls": {"regular": REGULAR, "original": ORIGINAL},
+            "tags": {"tags": [
+                {"tag": "オリジナル", "translation": {"en": "original"}},
+                {"tag": "漫画"},
+                {"translation": {"en": "broken"}},
+            ]},
+            "seriesNavData": null,
+        });
+        let artwork = Artwork::try_from(&value).unwrap();
+        assert_eq!(artwork.artwork_id, 84000001);
+        assert_eq!(artwork.page_count, Some(3));
+        assert!(artwork.pages.is_empty());
+        assert_eq!(artwork.width, Some(1200));
+        assert_eq!(artwork.image_urls.unwrap().large.as_deref(), Some(ORIGINAL));
+        let user = artwork.user.unwrap();
+        assert_eq!(user.user_id, Some(11));
+        assert_eq!(user.name.as_deref(), Some("pixiv事務局"));
+        let tags = artwork.tags.unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0].trans.as_deref(), Some("original"));
+        assert_eq!(tags[1].trans, None);
+        assert!(artwork.series.is_none());
+
+        assert!(Artwork::try_from(&serde_json::json!({"id": 84000001})).is_err());
+        assert!(Artwork::try_from(&serde_json::json!({"id": "abc"})).is_err());
+        assert!(Artwork::try_from(&serde_json::json!({"title": "t"})).is_err());
+        let artwork = Artwork::try_from(&serde_json::json!({"id": "1"})).unwrap();
+        assert_eq!(artwork.page_count, None);
+    }
+
+    #[test]
+    fn artwork_type_from_illust_type() {
+        assert_eq!(ArtworkType::from(0), ArtworkType::Illust);
+        assert_eq!(ArtworkType::from(1), ArtworkType::Manga);
+        assert_eq!(ArtworkType::from(2), ArtworkType::Ugoira);
+        assert_eq!(ArtworkType::from(3), ArtworkType::Unknown);
+        for (illust_type, expected) in &[(0, "illust"), (1, "manga"), (2, "ugoira")] {
+            let value = serde_json::json!({"id": "1", "illustType": illust_type});
+            let artwork = Artwork::try_from(&value).unwrap();
+            assert_eq!(
+                serde_json::to_value(artwork.artwork_type).unwrap(),
+                serde_json::json!(expected)
+            );
+        }
+        // 旧数据中的 "unknow" 仍然可以读取, 写入时保持原样
+        let unknown: ArtworkType = serde_json::from_str("\"unknow\"").unwrap();
+        assert_eq!(unknown, ArtworkType::Unknown);
+        assert_eq!(serde_json::to_string(&unknown).unwrap(), "\"unknow\"");
+        assert!(serde_json::from_str::<ArtworkType>("\"unknown\"").is_err());
+        assert!(Artwork::try_from(&serde_json::json!({"id": "1", "illustType": "2"})).is_err());
+    }
+
+    #[test]
+    fn ugoira_meta_from_json() {
+        let value = serde_json::json!({
+            "src": "https://i.pximg.net/img-zip-ugoira/img/2020/12/01/00/00/00/84000001_ugoira600x600.zip",
+            "originalSrc": "https://i.pximg.net/img-zip-ugoira/img/2020/12/01/00/00/00/84000001_ugoira1920x1080.zip",
+            "mime_type": "image/jpeg",
+            "frames": [
+                {"file": "000000.jpg", "delay": 80},
+                {"file": "000001.jpg", "delay": 120},
+                {"file": "000002.jpg"},
+            ],
+        });
+        let meta = UgoiraMeta::try_from(&value).unwrap();
+        assert!(meta.original_src.unwrap().ends_with("1920x1080.zip"));
+        assert_eq!(meta.mime_type.as_deref(), Some("image/jpeg"));
+        let frames: Vec<(&str, i32)> = meta
+            .frames
+            .iter()
+            .map(|x| (x.file.as_str(), x.delay))
+            .collect();
+        // 缺少 delay 时按 100 毫秒
+        assert_eq!(
+            frames,
+            vec![("000000.jpg", 80), ("000001.jpg", 120), ("000002.jpg", 100)]
+        );
+
+        assert!(UgoiraMeta::try_from(&serde_json::json!({}))
+            .unwrap()
+            .frames
+            .is_empty());
+        assert!(UgoiraMeta::try_from(&serde_json::json!({"frames": [{"delay": 1}]})).is_err());
+        assert!(UgoiraMeta::try_from(&serde_json::json!({"frames": {}})).is_err());
+    }
+
+    #[test]
+    fn series_nav_from_json() {
+        let nav = PixivSeriesNav::try_from(&serde_json::json!({
+            "seriesType": "manga",
+            "seriesId": "120001",
+            "title": "シリーズ",
+            "order": 3,
+        }))
+        .unwrap();
+        assert_eq!(nav.series_id, 120001);
+        assert_eq!(nav.title.as_deref(), Some("シリーズ"));
+        assert_eq!(nav.order, Some(3));
+        // 小说的 seriesId 是数字
+        let nav = PixivSeriesNav::try_from(&serde_json::json!({"seriesId": 120002})).unwrap();
+        assert_eq!(nav.series_id, 120002);
+        assert_eq!(nav.order, None);
+        assert!(PixivSeriesNav::try_from(&serde_json::json!({"title": "x"})).is_err());
+        assert!(PixivSeriesNav::try_from(&serde_json::json!({"seriesId": "abc"})).is_err());
+        assert!(
+            PixivSeriesNav::try_from(&serde_json::json!({"seriesId": 1, "order": "3"})).is_err()
+        );
+
+        let artwork = Artwork::try_from(&serde_json::json!({
+            "id": "84000001",
+            "seriesNavData": {"seriesId": "120001", "order": 2},
+        }))
+        .unwrap();
+        assert_eq!(artwork.series.unwrap().order, Some(2));
+    }
+}
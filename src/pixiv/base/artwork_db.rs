@@ -36,6 +36,12 @@ macro_rules! field_map {
     (ImageUrls) => {
         "image_urls"
     };
+    (PageCount) => {
+        "page_count"
+    };
+    (Pages) => {
+        "pages"
+    };
     (LastUpdateTime) => {
         "last_update_time"
     };
@@ -69,6 +75,36 @@ macro_rules! field_map {
     (TagTrans) => {
         "translated_name"
     };
+    (Ugoira) => {
+        "ugoira"
+    };
+    (Files) => {
+        "files"
+    };
+    (Rankings) => {
+        "rankings"
+    };
+    (RankingMode) => {
+        "mode"
+    };
+    (RankingContent) => {
+        "content"
+    };
+    (RankingDate) => {
+        "date"
+    };
+    (RankingRank) => {
+        "rank"
+    };
+    (Series) => {
+        "series"
+    };
+    (SeriesID) => {
+        "id"
+    };
+    (SeriesOrder) => {
+        "order"
+    };
 }
 #[macro_export]
 macro_rules! field_mapi {
@@ -108,6 +144,12 @@ macro_rules! field_mapi {
     (ImageUrls) => {
         "image_urls"
     };
+    (PageCount) => {
+        "page_count"
+    };
+    (Pages) => {
+        "pages"
+    };
     (LastUpdateTime) => {
         "last_update_time"
     };
@@ -141,6 +183,36 @@ macro_rules! field_mapi {
     (TagTrans) => {
         "tags.translated_name"
     };
+    (Ugoira) => {
+        "ugoira"
+    };
+    (Files) => {
+        "files"
+    };
+    (Rankings) => {
+        "rankings"
+    };
+    (RankingMode) => {
+        "rankings.mode"
+    };
+    (RankingContent) => {
+        "rankings.content"
+    };
+    (RankingDate) => {
+        "rankings.date"
+    };
+    (RankingRank) => {
+        "rankings.rank"
+    };
+    (Series) => {
+        "series"
+    };
+    (SeriesID) => {
+        "series.id"
+    };
+    (SeriesOrder) => {
+        "series.order"
+    };
 }
 
 // macro_rules! put_value {
@@ -0,0 +1,256 @@
+use super::transport::{ResponseBody, Transport, TransportFuture, TransportResponse};
+use super::PixivError;
+use futures::{io::Cursor, AsyncReadExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+/// cassette 目录中每个请求对应 `<key>.json` (请求与响应头) 和 `<key>.body` (响应体) 两个文件
+#[derive(Serialize, Deserialize)]
+struct CassetteEntry {
+    method: String,
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+}
+
+fn cassette_key(method: &str, url: &str) -> String {
+    hex::encode(Sha256::digest(format!("{} {}", method, url).as_bytes()))
+}
+
+const REDACTED: &str = "redacted";
+
+/// cassette 会被提交到仓库, `Set-Cookie` 的值 (例如 `PHPSESSID`) 替换为占位符后再写入
+///
+/// 保留 Cookie 名和属性, 删除 Cookie 的响应原样保留, 回放时仍能模拟会话被删除
+fn redact_set_cookie(value: &str) -> String {
+    let (pair, attrs) = match value.find(';') {
+        Some(pos) => (&value[..pos], &value[pos..]),
+        None => (value, ""),
+    };
+    let (name, cookie) = match pair.find('=') {
+        Some(pos) => (&pair[..pos], pair[pos + 1..].trim()),
+        None => return value.to_string(),
+    };
+    if cookie.is_empty() || cookie == "deleted" {
+        return value.to_string();
+    }
+    format!("{}={}{}", name, REDACTED, attrs)
+}
+
+fn entry_paths(dir: &Path, key: &str) -> (PathBuf, PathBuf) {
+    (
+        dir.join(format!("{}.json", key)),
+        dir.join(format!("{}.body", key)),
+    )
+}
+
+/// 转发请求到内部传输层, 并把每一对请求/响应写入 cassette 目录
+pub struct RecordingTransport {
+    inner: Box<dyn Transport>,
+    dir: PathBuf,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Box<dyn Transport>, dir: impl Into<PathBuf>) -> RecordingTransport {
+        RecordingTransport {
+            inner,
+            dir: dir.into(),
+        }
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn send(&self, request: http::Request<()>) -> TransportFuture<'_> {
+        let method = request.method().to_string();
+        let url = request.uri().to_string();
+        Box::pin(async move {
+            let mut response = self.inner.send(request).await?;
+            let mut body = Vec::new();
+            response.body.read_to_end(&mut body).await?;
+
+            // 请求头 (包括 Cookie) 不写入 cassette, 响应头中的 Set-Cookie 写入前脱敏
+            let entry = CassetteEntry {
+                method: method.clone(),
+                url: url.clone(),
+                status: response.status,
+                headers: response
+                    .headers
+                    .iter()
+                    .filter_map(|(k, v)| {
+                        let v = v.to_str().ok()?;
+                        let v = if k == http::header::SET_COOKIE {
+                            redact_set_cookie(v)
+                        } else {
+                            v.to_string()
+                        };
+                        Some((k.as_str().to_string(), v))
+                    })
+                    .collect(),
+            };
+            let (json_path, body_path) = entry_paths(&self.dir, &cassette_key(&method, &url));
+            async_std::fs::create_dir_all(&self.dir).await?;
+            async_std::fs::write(&body_path, &body).await?;
+            async_std::fs::write(&json_path, serde_json::to_vec_pretty(&entry).unwrap()).await?;
+
+            Ok(TransportResponse {
+                status: response.status,
+                headers: response.headers,
+                body: Box::new(Cursor::new(body)) as ResponseBody,
+            })
+        })
+    }
+}
+
+/// 从 cassette 目录回放录制好的响应, 不访问网络
+pub struct ReplayTransport {
+    dir: PathBuf,
+}
+
+impl ReplayTransport {
+    pub fn new(dir: impl Into<PathBuf>) -> ReplayTransport {
+        ReplayTransport { dir: dir.into() }
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send(&self, request: http::Request<()>) -> TransportFuture<'_> {
+        let method = request.method().to_string();
+        let url = request.uri().to_string();
+        Box::pin(async move {
+            let (json_path, body_path) = entry_paths(&self.dir, &cassette_key(&method, &url));
+            let json = match async_std::fs::read_to_string(&json_path).await {
+                Ok(x) => x,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    return Err(PixivError::ClientIoError(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("cassette 中没有录制该请求: {} {}", method, url),
+                    )))
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let entry: CassetteEntry = match serde_json::from_str(&json) {
+                Ok(x) => x,
+                Err(e) => {
+                    return Err(PixivError::ParseJSONError(
+                        json_path.to_string_lossy().to_string(),
+                        e.to_string(),
+                    ))
+                }
+            };
+            let body = async_std::fs::read(&body_path).await?;
+            let mut headers = http::HeaderMap::new();
+            for (k, v) in &entry.headers {
+                let name = match http::header::HeaderName::from_bytes(k.as_bytes()) {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                };
+                if let Ok(value) = http::HeaderValue::from_str(v) {
+                    headers.append(name, value);
+                }
+            }
+            Ok(TransportResponse {
+                status: entry.status,
+                headers,
+                body: Box::new(Cursor::new(body)) as ResponseBody,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::transport::{Fixture, FixtureTransport};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("pixiv-cassette-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn send(
+        transport: &dyn Transport,
+        url: &str,
+    ) -> Result<(u16, http::HeaderMap, Vec<u8>), PixivError> {
+        async_std::task::block_on(async {
+            let request = http::Request::get(url).body(()).unwrap();
+            let mut response = transport.send(request).await?;
+            let mut body = Vec::new();
+            response.body.read_to_end(&mut body).await?;
+            Ok((response.status, response.headers, body))
+        })
+    }
+
+    #[test]
+    fn redact_keeps_name_and_attributes() {
+        assert_eq!(
+            redact_set_cookie("PHPSESSID=123_secret; path=/; HttpOnly"),
+            "PHPSESSID=redacted; path=/; HttpOnly"
+        );
+        assert_eq!(redact_set_cookie("a=1"), "a=redacted");
+        assert_eq!(
+            redact_set_cookie("PHPSESSID=deleted; Max-Age=0"),
+            "PHPSESSID=deleted; Max-Age=0"
+        );
+        assert_eq!(
+            redact_set_cookie("PHPSESSID=; Max-Age=0"),
+            "PHPSESSID=; Max-Age=0"
+        );
+    }
+
+    #[test]
+    fn record_then_replay() {
+        let dir = temp_dir("replay");
+        let url = "https://www.pixiv.net/ajax/illust/1/pages?lang=zh";
+        let mut fixtures = FixtureTransport::new();
+        fixtures.insert(
+            url,
+            Fixture {
+                status: 200,
+                headers: vec![
+                    ("content-type".into(), "application/json".into()),
+                    ("set-cookie".into(), "PHPSESSID=123_secret; path=/".into()),
+                ],
+                body: b"{\"body\":[]}".to_vec(),
+            },
+        );
+        let recorder = RecordingTransport::new(Box::new(fixtures), &dir);
+        let (status, headers, body) = send(&recorder, url).unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body, b"{\"body\":[]}");
+        // 录制时调用方仍然拿到原始响应
+        assert_eq!(
+            headers[http::header::SET_COOKIE],
+            "PHPSESSID=123_secret; path=/"
+        );
+
+        let key = cassette_key("GET", url);
+        let json = std::fs::read_to_string(dir.join(format!("{}.json", key))).unwrap();
+        assert!(!json.contains("123_secret"));
+
+        let replay = ReplayTransport::new(&dir);
+        let (status, headers, body) = send(&replay, url).unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body, b"{\"body\":[]}");
+        assert_eq!(headers[http::header::CONTENT_TYPE], "application/json");
+        assert_eq!(
+            headers[http::header::SET_COOKIE],
+            "PHPSESSID=redacted; path=/"
+        );
+
+        match send(&replay, "https://www.pixiv.net/ajax/illust/2/pages") {
+            Err(PixivError::ClientIoError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+            x => panic!("{:?}", x.map(|x| x.0)),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
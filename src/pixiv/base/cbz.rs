@@ -0,0 +1,264 @@
+use super::artwork::{Artwork, ArtworkType};
+use super::embed::escape_xml;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(thiserror::Error, Debug)]
+pub enum CbzError {
+    #[error("压缩包错误 : {0:?}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("IO错误 : {0:?}")]
+    Io(#[from] std::io::Error),
+    #[error("作品 {0} 没有已下载的图片")]
+    NoFiles(i64),
+}
+
+/// 漫画或多页作品才需要打包
+pub fn is_multi_page(artwork: &Artwork) -> bool {
+    artwork.artwork_type == Some(ArtworkType::Manga)
+        || artwork.page_count.unwrap_or(1) > 1
+        || artwork.files.len() > 1
+}
+
+/// 简介是 HTML, 换行标签转成换行, 其他标签去掉
+fn caption_text(caption: &str) -> String {
+    let caption = caption
+        .replace("<br />", "\n")
+        .replace("<br/>", "\n")
+        .replace("<br>", "\n");
+    let mut out = String::with_capacity(caption.len());
+    let mut in_tag = false;
+    for c in caption.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => (),
+        }
+    }
+    out.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// ComicRack 的 ComicInfo.xml, 标签包括翻译
+pub fn build_comic_info(artwork: &Artwork) -> String {
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    if let Some(ref title) = artwork.title {
+        fields.push(("Title", title.clone()));
+    }
+    if let Some(ref caption) = artwork.caption {
+        let caption = caption_text(caption);
+        if !caption.trim().is_empty() {
+            fields.push(("Summary", caption));
+        }
+    }
+    if let Some(ref date) = artwork.create_date {
+        if let Ok(date) = chrono::DateTime::parse_from_rfc3339(date) {
+            use chrono::Datelike;
+            fields.push(("Year", date.year().to_string()));
+            fields.push(("Month", date.month().to_string()));
+            fields.push(("Day", date.day().to_string()));
+        }
+    }
+    if let Some(name) = artwork.user.as_ref().and_then(|x| x.name.as_ref()) {
+        fields.push(("Writer", name.clone()));
+        fields.push(("Penciller", name.clone()));
+    }
+    let mut tags = Vec::new();
+    for tag in artwork.tags.iter().flatten() {
+        tags.push(tag.name.clone());
+        if let Some(ref trans) = tag.trans {
+            tags.push(trans.clone());
+        }
+    }
+    if !tags.is_empty() {
+        fields.push(("Tags", tags.join(", ")));
+    }
+    fields.push((
+        "Web",
+        format!("https://www.pixiv.net/artworks/{}", artwork.artwork_id),
+    ));
+    fields.push(("PageCount", artwork.files.len().to_string()));
+    if artwork.artwork_type == Some(ArtworkType::Manga) {
+        fields.push(("Manga", "Yes".to_string()));
+    }
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <ComicInfo xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+         xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\">\n",
+    );
+    for (name, value) in fields {
+        xml.push_str(&format!("  <{0}>{1}</{0}>\n", name, escape_xml(&value)));
+    }
+    xml.push_str("  <Pages>\n");
+    for index in 0..artwork.files.len() {
+        if index == 0 {
+            xml.push_str("    <Page Image=\"0\" Type=\"FrontCover\" />\n");
+        } else {
+            xml.push_str(&format!("    <Page Image=\"{}\" />\n", index));
+        }
+    }
+    xml.push_str("  </Pages>\n</ComicInfo>\n");
+    xml
+}
+
+/// 把已下载的各页按页码顺序打包成 CBZ, 页面文件名补零以保证排序
+///
+/// 图片本身已经压缩, 不再压缩; ComicInfo.xml 放在压缩包根目录
+pub fn export_cbz(artwork: &Artwork, output: &Path) -> Result<(), CbzError> {
+    if artwork.files.is_empty() {
+        return Err(CbzError::NoFiles(artwork.artwork_id));
+    }
+    let mut files = artwork.files.iter().collect::<Vec<_>>();
+    files.sort_by_key(|x| x.page);
+    let width = files.len().to_string().len().max(3);
+
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut temp = output.as_os_str().to_owned();
+    temp.push(".part");
+    {
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&temp)?);
+        let stored =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (index, file) in files.iter().enumerate() {
+            let ext = Path::new(&file.path)
+                .extension()
+                .map(|x| x.to_string_lossy().to_string())
+                .unwrap_or_else(|| "jpg".to_string());
+            writer.start_file(format!("{:0width$}.{}", index, ext, width = width), stored)?;
+            let mut source = std::fs::File::open(&file.path)?;
+            std::io::copy(&mut source, &mut writer)?;
+        }
+        writer.start_file(
+            "ComicInfo.xml",
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated),
+        )?;
+        writer.write_all(build_comic_info(artwork).as_bytes())?;
+        writer.finish()?;
+    }
+    std::fs::rename(&temp, output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::artwork::PixivFile;
+    use super::*;
+    use std::convert::TryFrom;
+    use std::io::Read;
+
+    fn artwork(value: serde_json::Value) -> Artwork {
+        Artwork::try_from(&value).unwrap()
+    }
+
+    fn file(page: i32, path: &Path) -> PixivFile {
+        PixivFile {
+            page,
+            url: format!("https://i.pximg.net/84000001_p{}.png", page),
+            path: path.to_string_lossy().to_string(),
+            size: 0,
+            sha256: String::new(),
+            thumbnails: Vec::new(),
+            phash: None,
+        }
+    }
+
+    #[test]
+    fn multi_page_detection() {
+        let single = artwork(serde_json::json!({"id": "1", "illustType": 0, "pageCount": 1}));
+        assert!(!is_multi_page(&single));
+        let manga = artwork(serde_json::json!({"id": "1", "illustType": 1, "pageCount": 1}));
+        assert!(is_multi_page(&manga));
+        let pages = artwork(serde_json::json!({"id": "1", "illustType": 0, "pageCount": 3}));
+        assert!(is_multi_page(&pages));
+        let mut files = artwork(serde_json::json!({"id": "1", "illustType": 0}));
+        files.files = vec![file(0, Path::new("a.png")), file(1, Path::new("b.png"))];
+        assert!(is_multi_page(&files));
+    }
+
+    #[test]
+    fn comic_info_without_caption_or_user() {
+        let mut artwork = artwork(serde_json::json!({"id": "84000001", "illustType": 0}));
+        artwork.files = vec![file(0, Path::new("a.png"))];
+        let xml = build_comic_info(&artwork);
+        assert!(!xml.contains("<Title>"));
+        assert!(!xml.contains("<Summary>"));
+        assert!(!xml.contains("<Writer>"));
+        assert!(!xml.contains("<Manga>"));
+        assert!(xml.contains("<Web>https://www.pixiv.net/artworks/84000001</Web>"));
+        assert!(xml.contains("<PageCount>1</PageCount>"));
+        assert!(xml.contains("<Page Image=\"0\" Type=\"FrontCover\" />"));
+
+        // 只有换行和标签的简介视为空
+        artwork.caption = Some("<br /><strong></strong>".to_string());
+        assert!(!build_comic_info(&artwork).contains("<Summary>"));
+    }
+
+    #[test]
+    fn export_cbz_orders_pages_and_writes_comic_info() {
+        let dir = std::env::temp_dir().join(format!("pixiv-cbz-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut artwork = artwork(serde_json::json!({
+            "id": "84000001",
+            "title": "A & B <1>",
+            "illustType": 1,
+            "illustComment": "一行<br />二行 &amp; <a href=\"x\">链接</a>",
+            "createDate": "2020-12-01T09:00:00+09:00",
+            "userId": "11",
+            "userName": "作者\"名\"",
+            "tags": {"tags": [{"tag": "漫画", "translation": {"en": "manga"}}]},
+        }));
+        // 页码乱序、扩展名不同, 打包后按页码排序
+        for (page, ext) in &[(2, "jpg"), (0, "png"), (1, "png")] {
+            let path = dir.join(format!("84000001_p{}.{}", page, ext));
+            std::fs::write(&path, format!("page{}", page)).unwrap();
+            artwork.files.push(file(*page, &path));
+        }
+        let output = dir.join("out").join("84000001.cbz");
+        export_cbz(&artwork, &output).unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&output).unwrap()).unwrap();
+        let names: Vec<String> = (0..archive.len())
+            .map(|x| archive.by_index(x).unwrap().name().to_string())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["000.png", "001.png", "002.jpg", "ComicInfo.xml"]
+        );
+        let mut content = String::new();
+        archive
+            .by_name("002.jpg")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "page2");
+
+        let mut xml = String::new();
+        archive
+            .by_name("ComicInfo.xml")
+            .unwrap()
+            .read_to_string(&mut xml)
+            .unwrap();
+        assert!(xml.contains("<Title>A &amp; B &lt;1&gt;</Title>"));
+        assert!(xml.contains("<Summary>一行\n二行 &amp; 链接</Summary>"));
+        assert!(xml.contains("<Year>2020</Year>"));
+        assert!(xml.contains("<Writer>作者&quot;名&quot;</Writer>"));
+        assert!(xml.contains("<Tags>漫画, manga</Tags>"));
+        assert!(xml.contains("<PageCount>3</PageCount>"));
+        assert!(xml.contains("<Manga>Yes</Manga>"));
+        assert!(xml.contains("<Page Image=\"2\" />"));
+        assert!(!output.with_extension("cbz.part").exists());
+
+        artwork.files.clear();
+        match export_cbz(&artwork, &dir.join("empty.cbz")) {
+            Err(CbzError::NoFiles(84000001)) => (),
+            x => panic!("{:?}", x),
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,392 @@
+use super::artwork::Artwork;
+use std::path::Path;
+
+#[derive(thiserror::Error, Debug)]
+pub enum EmbedError {
+    #[error("IO错误 : {0:?}")]
+    Io(#[from] std::io::Error),
+    #[error("文件格式错误 : {0}")]
+    BadFormat(String),
+}
+
+type Result<T> = std::result::Result<T, EmbedError>;
+
+/// 写入图片文件的作品信息
+#[derive(Debug, Clone)]
+pub struct EmbeddedMetadata {
+    pub artwork_id: i64,
+    pub title: Option<String>,
+    pub user_id: Option<i64>,
+    pub user_name: Option<String>,
+    /// (标签, 翻译)
+    pub tags: Vec<(String, Option<String>)>,
+}
+
+impl EmbeddedMetadata {
+    pub fn from_artwork(artwork: &Artwork) -> EmbeddedMetadata {
+        EmbeddedMetadata {
+            artwork_id: artwork.artwork_id,
+            title: artwork.title.clone(),
+            user_id: artwork.user.as_ref().and_then(|x| x.user_id),
+            user_name: artwork.user.as_ref().and_then(|x| x.name.clone()),
+            tags: artwork
+                .tags
+                .iter()
+                .flatten()
+                .map(|x| (x.name.clone(), x.trans.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("https://www.pixiv.net/artworks/{}", self.artwork_id)
+    }
+
+    /// 标签和翻译平铺成关键词列表
+    fn keywords(&self) -> Vec<&str> {
+        let mut keywords = Vec::new();
+        for (name, trans) in &self.tags {
+            keywords.push(name.as_str());
+            if let Some(trans) = trans {
+                keywords.push(trans.as_str());
+            }
+        }
+        keywords
+    }
+}
+
+pub fn escape_xml(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const PNG_XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+
+/// 生成 XMP 包: 通用字段用 Dublin Core, 标签翻译放在 pixiv 命名空间下
+pub fn build_xmp(meta: &EmbeddedMetadata) -> String {
+    let mut body = String::new();
+    if let Some(ref title) = meta.title {
+        body.push_str(&format!(
+            "   <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n",
+            escape_xml(title)
+        ));
+    }
+    if let Some(ref name) = meta.user_name {
+        body.push_str(&format!(
+            "   <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n",
+            escape_xml(name)
+        ));
+    }
+    let keywords = meta.keywords();
+    if !keywords.is_empty() {
+        body.push_str("   <dc:subject><rdf:Bag>");
+        for keyword in keywords {
+            body.push_str(&format!("<rdf:li>{}</rdf:li>", escape_xml(keyword)));
+        }
+        body.push_str("</rdf:Bag></dc:subject>\n");
+    }
+    body.push_str(&format!("   <dc:source>{}</dc:source>\n", meta.url()));
+    body.push_str(&format!(
+        "   <dc:identifier>{}</dc:identifier>\n",
+        meta.artwork_id
+    ));
+    body.push_str(&format!(
+        "   <pixiv:ArtworkId>{}</pixiv:ArtworkId>\n",
+        meta.artwork_id
+    ));
+    if let Some(user_id) = meta.user_id {
+        body.push_str(&format!("   <pixiv:UserId>{}</pixiv:UserId>\n", user_id));
+    }
+    if !meta.tags.is_empty() {
+        body.push_str("   <pixiv:Tags><rdf:Seq>");
+        for (name, trans) in &meta.tags {
+            body.push_str(&format!(
+                "<rdf:li rdf:parseType=\"Resource\"><pixiv:Name>{}</pixiv:Name>",
+                escape_xml(name)
+            ));
+            if let Some(trans) = trans {
+                body.push_str(&format!(
+                    "<pixiv:Translation>{}</pixiv:Translation>",
+                    escape_xml(trans)
+                ));
+            }
+            body.push_str("</rdf:li>");
+        }
+        body.push_str("</rdf:Seq></pixiv:Tags>\n");
+    }
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         \x20<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         \x20 <rdf:Description rdf:about=\"\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+         xmlns:pixiv=\"https://www.pixiv.net/\">\n\
+         {}\
+         \x20 </rdf:Description>\n\
+         \x20</rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>",
+        body
+    )
+}
+
+/// EXIF 的 TIFF 结构 (大端序), 只有 IFD0:
+/// ImageDescription = 标题, Artist = 作者, Copyright = 作品链接, XPKeywords = 标签
+fn build_exif(meta: &EmbeddedMetadata) -> Vec<u8> {
+    // (tag, type, 数据) type 2 = ASCII, 1 = BYTE
+    let mut entries: Vec<(u16, u16, Vec<u8>)> = Vec::new();
+    let ascii = |value: &str| {
+        let mut data = value.as_bytes().to_vec();
+        data.push(0);
+        data
+    };
+    if let Some(ref title) = meta.title {
+        entries.push((0x010E, 2, ascii(title)));
+    }
+    if let Some(ref name) = meta.user_name {
+        entries.push((0x013B, 2, ascii(name)));
+    }
+    entries.push((0x8298, 2, ascii(&meta.url())));
+    let keywords = meta.keywords();
+    if !keywords.is_empty() {
+        // XPKeywords 是 UTF-16LE, 以分号分隔
+        let mut data = Vec::new();
+        for unit in keywords.join(";").encode_utf16().chain(std::iter::once(0)) {
+            data.extend_from_slice(&unit.to_le_bytes());
+        }
+        entries.push((0x9C9E, 1, data));
+    }
+
+    let mut tiff = vec![b'M', b'M', 0, 0x2A, 0, 0, 0, 8];
+    let ifd_size = 2 + entries.len() * 12 + 4;
+    let mut data_offset = 8 + ifd_size;
+    let mut data_area = Vec::new();
+    tiff.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+    for (tag, kind, data) in &entries {
+        tiff.extend_from_slice(&tag.to_be_bytes());
+        tiff.extend_from_slice(&kind.to_be_bytes());
+        tiff.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        if data.len() <= 4 {
+            let mut value = data.clone();
+            value.resize(4, 0);
+            tiff.extend_from_slice(&value);
+        } else {
+            tiff.extend_from_slice(&(data_offset as u32).to_be_bytes());
+            data_area.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                data_area.push(0);
+            }
+            data_offset = 8 + ifd_size + data_area.len();
+        }
+    }
+    tiff.extend_from_slice(&[0, 0, 0, 0]);
+    tiff.extend_from_slice(&data_area);
+    let mut exif = b"Exif\0\0".to_vec();
+    exif.extend_from_slice(&tiff);
+    exif
+}
+
+fn jpeg_segment(marker: u8, payload: &[u8]) -> Result<Vec<u8>> {
+    // 段长度包括长度字段本身的 2 字节
+    if payload.len() + 2 > u16::MAX as usize {
+        return Err(EmbedError::BadFormat(format!(
+            "JPEG 段过长 : {} 字节",
+            payload.len()
+        )));
+    }
+    let mut segment = vec![0xFF, marker];
+    segment.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+    segment.extend_from_slice(payload);
+    Ok(segment)
+}
+
+/// 在 SOI 和 JFIF 的 APP0 之后插入 EXIF 和 XMP 两个 APP1 段, 已有 XMP 时返回 None
+fn embed_jpeg(data: &[u8], meta: &EmbeddedMetadata) -> Result<Option<Vec<u8>>> {
+    let mut pos = 2;
+    let mut insert_at = 2;
+    // 只扫描 SOS 之前的段
+    while pos + 4 <= data.len() && data[pos] == 0xFF {
+        let marker = data[pos + 1];
+        if marker == 0xDA {
+            break;
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        if length < 2 || pos + 2 + length > data.len() {
+            return Err(EmbedError::BadFormat("JPEG 段长度错误".to_string()));
+        }
+        let payload = &data[pos + 4..pos + 2 + length];
+        if marker == 0xE1 && payload.starts_with(XMP_NAMESPACE) {
+            return Ok(None);
+        }
+        pos += 2 + length;
+        if marker == 0xE0 {
+            insert_at = pos;
+        }
+    }
+    let mut xmp = XMP_NAMESPACE.to_vec();
+    xmp.extend_from_slice(build_xmp(meta).as_bytes());
+    let mut out = Vec::with_capacity(data.len() + xmp.len() + 1024);
+    out.extend_from_slice(&data[..insert_at]);
+    out.extend_from_slice(&jpeg_segment(0xE1, &build_exif(meta))?);
+    out.extend_from_slice(&jpeg_segment(0xE1, &xmp)?);
+    out.extend_from_slice(&data[insert_at..]);
+    Ok(Some(out))
+}
+
+fn png_chunk(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(payload.len() + 12);
+    chunk.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(payload);
+    let mut crc = flate2::Crc::new();
+    crc.update(kind);
+    crc.update(payload);
+    chunk.extend_from_slice(&crc.sum().to_be_bytes());
+    chunk
+}
+
+/// 未压缩的 iTXt 块, 文本为 UTF-8
+fn png_itxt(keyword: &str, text: &str) -> Vec<u8> {
+    let mut payload = keyword.as_bytes().to_vec();
+    // 关键字结束符, 压缩标志, 压缩方法, 空语言标签, 空翻译关键字
+    payload.extend_from_slice(&[0, 0, 0, 0, 0]);
+    payload.extend_from_slice(text.as_bytes());
+    png_chunk(b"iTXt", &payload)
+}
+
+/// 在 IHDR 之后插入 iTXt 块, 已有 XMP 时返回 None
+fn embed_png(data: &[u8], meta: &EmbeddedMetadata) -> Result<Option<Vec<u8>>> {
+    let mut pos = 8;
+    let mut insert_at = None;
+    while pos + 8 <= data.len() {
+        let length =
+            u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        if pos + 12 + length > data.len() {
+            return Err(EmbedError::BadFormat("PNG 块长度错误".to_string()));
+        }
+        let payload = &data[pos + 8..pos + 8 + length];
+        if kind == b"iTXt" && payload.starts_with(format!("{}\0", PNG_XMP_KEYWORD).as_bytes()) {
+            return Ok(None);
+        }
+        pos += 12 + length;
+        if kind == b"IHDR" {
+            insert_at = Some(pos);
+        }
+        if kind == b"IEND" {
+            break;
+        }
+    }
+    let insert_at = match insert_at {
+        Some(x) => x,
+        None => return Err(EmbedError::BadFormat("PNG 没有 IHDR 块".to_string())),
+    };
+    let mut chunks = Vec::new();
+    if let Some(ref title) = meta.title {
+        chunks.extend(png_itxt("Title", title));
+    }
+    if let Some(ref name) = meta.user_name {
+        chunks.extend(png_itxt("Author", name));
+    }
+    chunks.extend(png_itxt("Source", &meta.url()));
+    let keywords = meta.keywords();
+    if !keywords.is_empty() {
+        chunks.extend(png_itxt("Keywords", &keywords.join(", ")));
+    }
+    chunks.extend(png_itxt(PNG_XMP_KEYWORD, &build_xmp(meta)));
+    let mut out = Vec::with_capacity(data.len() + chunks.len());
+    out.extend_from_slice(&data[..insert_at]);
+    out.extend_from_slice(&chunks);
+    out.extend_from_slice(&data[insert_at..]);
+    Ok(Some(out))
+}
+
+/// 把作品信息写入图片文件: JPEG 写 EXIF + XMP, PNG 写 iTXt
+///
+/// 按文件头判断格式, 其他格式不处理; 已经写入过的文件不重复写入。返回是否修改了文件
+pub fn embed_metadata(path: &Path, meta: &EmbeddedMetadata) -> Result<bool> {
+    let data = std::fs::read(path)?;
+    let result = if data.starts_with(&[0xFF, 0xD8]) {
+        embed_jpeg(&data, meta)?
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        embed_png(&data, meta)?
+    } else {
+        None
+    };
+    let out = match result {
+        Some(x) => x,
+        None => return Ok(false),
+    };
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".part");
+    std::fs::write(&temp, &out)?;
+    std::fs::rename(&temp, path)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta() -> EmbeddedMetadata {
+        EmbeddedMetadata {
+            artwork_id: 1,
+            title: Some("タイトル <1>".into()),
+            user_id: Some(2),
+            user_name: Some("作者".into()),
+            tags: vec![("風景".into(), Some("scenery".into()))],
+        }
+    }
+
+    fn encode(format: image::ImageOutputFormat) -> Vec<u8> {
+        let mut out = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(8, 8, image::Rgb([1, 2, 3])))
+            .write_to(&mut std::io::Cursor::new(&mut out), format)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn png_chunks_are_valid_and_written_once() {
+        let data = embed_png(&encode(image::ImageOutputFormat::Png), &meta())
+            .unwrap()
+            .unwrap();
+        // png 解码器会校验每个块的 CRC
+        let mut reader = png::Decoder::new(std::io::Cursor::new(&data))
+            .read_info()
+            .unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut buffer).unwrap();
+        let texts: Vec<(String, String)> = reader
+            .info()
+            .utf8_text
+            .iter()
+            .map(|x| (x.keyword.clone(), x.get_text().unwrap()))
+            .collect();
+        assert!(texts.contains(&("Title".to_string(), "タイトル <1>".to_string())));
+        assert!(texts
+            .iter()
+            .any(|(k, v)| k == PNG_XMP_KEYWORD && v.contains("タイトル &lt;1&gt;")));
+        assert!(embed_png(&data, &meta()).unwrap().is_none());
+    }
+
+    #[test]
+    fn jpeg_still_decodes_and_is_written_once() {
+        let data = embed_jpeg(&encode(image::ImageOutputFormat::Jpeg(90)), &meta())
+            .unwrap()
+            .unwrap();
+        assert_eq!(image::load_from_memory(&data).unwrap().width(), 8);
+        assert!(embed_jpeg(&data, &meta()).unwrap().is_none());
+    }
+}
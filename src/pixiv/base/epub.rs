@@ -0,0 +1,606 @@
+use super::embed::escape_xml;
+use super::novel::Novel;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(thiserror::Error, Debug)]
+pub enum EpubError {
+    #[error("压缩包错误 : {0:?}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("IO错误 : {0:?}")]
+    Io(#[from] std::io::Error),
+    #[error("没有可导出的小说")]
+    Empty,
+    #[error("小说 {0} 没有正文")]
+    NoText(i64),
+}
+
+type Result<T> = std::result::Result<T, EpubError>;
+
+/// 正文按 `[newpage]` 和 `[chapter:]` 切分后的一段, 每段输出为一个 XHTML 文件
+struct Section {
+    /// `[chapter:]` 的标题, 保留 ruby 等标记
+    title: Option<String>,
+    text: String,
+    /// pixiv 的页码 (从 1 开始), 只由 `[newpage]` 决定, 用于 `[jump:]`
+    page: usize,
+}
+
+/// `[chapter:...]` 的标题中可能含有 `[[rb:...]]`, 按括号深度找结束位置
+fn chapter_title_end(text: &str) -> Option<usize> {
+    let mut depth = 1;
+    for (pos, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(pos);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+fn split_sections(text: &str) -> Vec<Section> {
+    let text = text.replace("\r\n", "\n");
+    let mut sections = vec![Section {
+        title: None,
+        text: String::new(),
+        page: 1,
+    }];
+    let mut page = 1;
+    let mut rest = text.as_str();
+    loop {
+        let newpage = rest.find("[newpage]");
+        let chapter = rest.find("[chapter:");
+        let pos = match (newpage, chapter) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(a), None) => a,
+            (None, Some(b)) => b,
+            (None, None) => {
+                sections.last_mut().unwrap().text.push_str(rest);
+                break;
+            }
+        };
+        sections.last_mut().unwrap().text.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let (title, next) = if let Some(next) = rest.strip_prefix("[newpage]") {
+            page += 1;
+            (None, next)
+        } else {
+            let start = "[chapter:".len();
+            match chapter_title_end(&rest[start..]) {
+                Some(end) => (
+                    Some(rest[start..start + end].trim().to_string()),
+                    &rest[start + end + 1..],
+                ),
+                // 没有闭合的括号按普通文本处理
+                None => {
+                    sections.last_mut().unwrap().text.push_str(rest);
+                    break;
+                }
+            }
+        };
+        // 紧跟在 [newpage] 后的 [chapter:] 不再单独分段
+        let current = sections.last_mut().unwrap();
+        if title.is_some() && current.title.is_none() && current.text.trim().is_empty() {
+            current.title = title;
+        } else {
+            sections.push(Section {
+                title,
+                text: String::new(),
+                page,
+            });
+        }
+        rest = next;
+    }
+    let mut sections: Vec<Section> = sections
+        .into_iter()
+        .filter(|x| x.title.is_some() || !x.text.trim().is_empty())
+        .collect();
+    if sections.is_empty() {
+        sections.push(Section {
+            title: None,
+            text: String::new(),
+            page: 1,
+        });
+    }
+    sections
+}
+
+/// 行内标记转为 XHTML: `[[rb:漢字 > かんじ]]` `[[jumpuri:文字 > 地址]]` `[jump:页码]` `[pixivimage:id]`
+///
+/// `jump` 根据页码返回对应的文件名
+fn inline_html(text: &str, jump: &dyn Fn(usize) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        if let Some((html, len)) = inline_tag(rest, jump) {
+            out.push_str(&html);
+            rest = &rest[len..];
+            continue;
+        }
+        let c = rest.chars().next().unwrap();
+        out.push_str(&escape_xml(c.encode_utf8(&mut [0; 4])));
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+fn split_arrow(inner: &str) -> (&str, &str) {
+    match inner.find('>') {
+        Some(pos) => (inner[..pos].trim(), inner[pos + 1..].trim()),
+        None => (inner.trim(), ""),
+    }
+}
+
+fn inline_tag(text: &str, jump: &dyn Fn(usize) -> Option<String>) -> Option<(String, usize)> {
+    if text.starts_with("[[rb:") {
+        let end = text.find("]]")?;
+        let (base, ruby) = split_arrow(&text["[[rb:".len()..end]);
+        let html = format!(
+            "<ruby>{}<rp>(</rp><rt>{}</rt><rp>)</rp></ruby>",
+            escape_xml(base),
+            escape_xml(ruby)
+        );
+        return Some((html, end + 2));
+    }
+    if text.starts_with("[[jumpuri:") {
+        let end = text.find("]]")?;
+        let (label, url) = split_arrow(&text["[[jumpuri:".len()..end]);
+        let html = if url.starts_with("http://") || url.starts_with("https://") {
+            format!("<a href=\"{}\">{}</a>", escape_xml(url), escape_xml(label))
+        } else {
+            escape_xml(label)
+        };
+        return Some((html, end + 2));
+    }
+    if text.starts_with("[jump:") {
+        let end = text.find(']')?;
+        let page = text["[jump:".len()..end].trim().parse::<usize>().ok()?;
+        let html = match jump(page) {
+            Some(href) => format!("<a href=\"{}\">→ {}</a>", href, page),
+            None => String::new(),
+        };
+        return Some((html, end + 1));
+    }
+    if text.starts_with("[pixivimage:") {
+        let end = text.find(']')?;
+        let id = text["[pixivimage:".len()..end].trim();
+        let artwork_id = id.split('-').next().unwrap_or(id);
+        let html = format!(
+            "<a href=\"https://www.pixiv.net/artworks/{0}\">pixiv {0}</a>",
+            escape_xml(artwork_id)
+        );
+        return Some((html, end + 1));
+    }
+    // 上传的插图不在本地, 直接去掉
+    if text.starts_with("[uploadedimage:") {
+        let end = text.find(']')?;
+        return Some((String::new(), end + 1));
+    }
+    None
+}
+
+fn paragraphs_html(text: &str, jump: &dyn Fn(usize) -> Option<String>) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let start = lines.iter().position(|x| !x.trim().is_empty()).unwrap_or(0);
+    let end = lines
+        .iter()
+        .rposition(|x| !x.trim().is_empty())
+        .map_or(0, |x| x + 1);
+    let mut out = String::new();
+    for line in &lines[start..end.max(start)] {
+        if line.trim().is_empty() {
+            out.push_str("<p><br /></p>\n");
+        } else {
+            out.push_str(&format!("<p>{}</p>\n", inline_html(line, jump)));
+        }
+    }
+    out
+}
+
+fn xhtml_page(language: &str, title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" \
+         xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"{0}\" lang=\"{0}\">\n\
+         <head>\n<meta charset=\"utf-8\" />\n<title>{1}</title>\n\
+         <link rel=\"stylesheet\" type=\"text/css\" href=\"../style.css\" />\n</head>\n\
+         <body>\n{2}</body>\n</html>\n",
+        language,
+        escape_xml(title),
+        body
+    )
+}
+
+const STYLE: &str = "body { line-height: 1.8; }\n\
+                     h1, h2 { text-align: center; }\n\
+                     p { margin: 0; text-indent: 0; }\n\
+                     .cover { text-align: center; }\n\
+                     .cover img { max-width: 100%; max-height: 100%; }\n";
+
+fn cover_media_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .map(|x| x.to_string_lossy().to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "image/jpeg",
+    }
+}
+
+/// 目录中的一项, `children` 为小说内的章节
+struct NavItem {
+    title: String,
+    href: String,
+    children: Vec<NavItem>,
+}
+
+fn nav_html(items: &[NavItem]) -> String {
+    let mut out = String::from("<ol>\n");
+    for item in items {
+        out.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>",
+            item.href,
+            inline_html(&item.title, &|_| None)
+        ));
+        if !item.children.is_empty() {
+            out.push('\n');
+            out.push_str(&nav_html(&item.children));
+        }
+        out.push_str("</li>\n");
+    }
+    out.push_str("</ol>\n");
+    out
+}
+
+/// 导出一篇小说或一个系列 (按传入顺序) 为 EPUB 3
+///
+/// 书名默认为第一篇小说的标题, 作者和封面取第一篇小说, 所有小说的标签作为主题。
+/// 封面由调用方传入本地文件路径, 不访问网络; 为 `None` 或文件不存在时不生成封面页
+pub fn export_epub(
+    novels: &[Novel],
+    title: Option<&str>,
+    cover: Option<&Path>,
+    output: &Path,
+) -> Result<()> {
+    let first = match novels.first() {
+        Some(x) => x,
+        None => return Err(EpubError::Empty),
+    };
+    let book_title = title
+        .map(|x| x.to_string())
+        .or_else(|| first.title.clone())
+        .unwrap_or_else(|| first.novel_id.to_string());
+    let author = first.user.as_ref().and_then(|x| x.name.clone());
+    let language = first.language.as_deref().unwrap_or("ja");
+    let identifier = if novels.len() == 1 {
+        format!("urn:pixiv:novel:{}", first.novel_id)
+    } else {
+        match first.series {
+            Some(ref x) => format!("urn:pixiv:novel-series:{}", x.series_id),
+            None => format!("urn:pixiv:novel:{}", first.novel_id),
+        }
+    };
+    let mut subjects: Vec<String> = Vec::new();
+    for tag in novels.iter().flat_map(|x| x.tags.iter().flatten()) {
+        if !subjects.contains(&tag.name) {
+            subjects.push(tag.name.clone());
+        }
+    }
+    let cover = cover.filter(|x| x.exists());
+
+    // (文件名, XHTML)
+    let mut pages: Vec<(String, String)> = Vec::new();
+    let mut nav: Vec<NavItem> = Vec::new();
+    for (index, novel) in novels.iter().enumerate() {
+        let text = match novel.text {
+            Some(ref x) => x,
+            None => return Err(EpubError::NoText(novel.novel_id)),
+        };
+        let novel_title = novel
+            .title
+            .clone()
+            .unwrap_or_else(|| novel.novel_id.to_string());
+        let sections = split_sections(text);
+        let file_name = |n: usize| format!("novel{:03}_{:03}.xhtml", index + 1, n + 1);
+        let jump = |page: usize| {
+            sections
+                .iter()
+                .position(|x| x.page == page)
+                .map(&file_name)
+        };
+        let mut chapters = Vec::new();
+        for (n, section) in sections.iter().enumerate() {
+            let mut body = String::new();
+            // 系列的每篇小说以标题开头
+            if n == 0 && novels.len() > 1 {
+                body.push_str(&format!("<h1>{}</h1>\n", escape_xml(&novel_title)));
+            }
+            if let Some(ref chapter) = section.title {
+                body.push_str(&format!("<h2>{}</h2>\n", inline_html(chapter, &|_| None)));
+                chapters.push(NavItem {
+                    title: chapter.clone(),
+                    href: format!("text/{}", file_name(n)),
+                    children: Vec::new(),
+                });
+            }
+            body.push_str(&paragraphs_html(&section.text, &jump));
+            let page_title = section.title.as_ref().unwrap_or(&novel_title);
+            pages.push((file_name(n), xhtml_page(language, page_title, &body)));
+        }
+        if novels.len() > 1 {
+            nav.push(NavItem {
+                title: novel_title,
+                href: format!("text/{}", file_name(0)),
+                children: chapters,
+            });
+        } else if chapters.is_empty() || chapters[0].href != format!("text/{}", file_name(0)) {
+            nav.push(NavItem {
+                title: novel_title,
+                href: format!("text/{}", file_name(0)),
+                children: Vec::new(),
+            });
+            nav.extend(chapters);
+        } else {
+            nav.extend(chapters);
+        }
+    }
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    manifest.push_str(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\" />\n\
+         <item id=\"style\" href=\"style.css\" media-type=\"text/css\" />\n",
+    );
+    let cover_file = cover.as_ref().map(|x| {
+        let ext = x
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_else(|| "jpg".to_string());
+        (format!("images/cover.{}", ext), cover_media_type(x))
+    });
+    if let Some((ref href, media_type)) = cover_file {
+        manifest.push_str(&format!(
+            "<item id=\"cover-image\" href=\"{}\" media-type=\"{}\" properties=\"cover-image\" />\n\
+             <item id=\"cover\" href=\"text/cover.xhtml\" media-type=\"application/xhtml+xml\" />\n",
+            href, media_type
+        ));
+        spine.push_str("<itemref idref=\"cover\" linear=\"no\" />\n");
+    }
+    for (n, (file_name, _)) in pages.iter().enumerate() {
+        manifest.push_str(&format!(
+            "<item id=\"page{}\" href=\"text/{}\" media-type=\"application/xhtml+xml\" />\n",
+            n + 1,
+            file_name
+        ));
+        spine.push_str(&format!("<itemref idref=\"page{}\" />\n", n + 1));
+    }
+    let mut metadata = format!(
+        "<dc:identifier id=\"book-id\">{}</dc:identifier>\n\
+         <dc:title>{}</dc:title>\n\
+         <dc:language>{}</dc:language>\n\
+         <meta property=\"dcterms:modified\">{}</meta>\n",
+        identifier,
+        escape_xml(&book_title),
+        language,
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+    );
+    if let Some(ref author) = author {
+        metadata.push_str(&format!(
+            "<dc:creator>{}</dc:creator>\n",
+            escape_xml(author)
+        ));
+    }
+    for subject in &subjects {
+        metadata.push_str(&format!(
+            "<dc:subject>{}</dc:subject>\n",
+            escape_xml(subject)
+        ));
+    }
+    if let Some(ref caption) = first.caption {
+        if novels.len() == 1 && !caption.trim().is_empty() {
+            metadata.push_str(&format!(
+                "<dc:description>{}</dc:description>\n",
+                escape_xml(caption)
+            ));
+        }
+    }
+    metadata.push_str(&format!(
+        "<dc:source>https://www.pixiv.net/novel/show.php?id={}</dc:source>\n",
+        first.novel_id
+    ));
+    if cover_file.is_some() {
+        metadata.push_str("<meta name=\"cover\" content=\"cover-image\" />\n");
+    }
+    let opf = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" \
+         unique-identifier=\"book-id\" xml:lang=\"{}\">\n\
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n{}</metadata>\n\
+         <manifest>\n{}</manifest>\n\
+         <spine>\n{}</spine>\n\
+         </package>\n",
+        language, metadata, manifest, spine
+    );
+    let nav_page = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" \
+         xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"{0}\" lang=\"{0}\">\n\
+         <head>\n<meta charset=\"utf-8\" />\n<title>{1}</title>\n</head>\n\
+         <body>\n<nav epub:type=\"toc\" id=\"toc\">\n<h1>{1}</h1>\n{2}</nav>\n</body>\n</html>\n",
+        language,
+        escape_xml(&book_title),
+        nav_html(&nav)
+    );
+
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut temp = output.as_os_str().to_owned();
+    temp.push(".part");
+    {
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&temp)?);
+        let stored =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let deflated =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        // mimetype 必须是第一个文件且不压缩
+        writer.start_file("mimetype", stored)?;
+        writer.write_all(b"application/epub+zip")?;
+        writer.start_file("META-INF/container.xml", deflated)?;
+        writer.write_all(
+            b"<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+              <container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+              <rootfiles>\n\
+              <rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\" />\n\
+              </rootfiles>\n\
+              </container>\n",
+        )?;
+        writer.start_file("OEBPS/content.opf", deflated)?;
+        writer.write_all(opf.as_bytes())?;
+        writer.start_file("OEBPS/nav.xhtml", deflated)?;
+        writer.write_all(nav_page.as_bytes())?;
+        writer.start_file("OEBPS/style.css", deflated)?;
+        writer.write_all(STYLE.as_bytes())?;
+        if let (Some(path), Some((href, _))) = (cover.as_ref(), cover_file.as_ref()) {
+            writer.start_file(format!("OEBPS/{}", href), stored)?;
+            writer.write_all(&std::fs::read(path)?)?;
+            writer.start_file("OEBPS/text/cover.xhtml", deflated)?;
+            let body = format!(
+                "<div class=\"cover\"><img src=\"../{}\" alt=\"{}\" /></div>\n",
+                href,
+                escape_xml(&book_title)
+            );
+            writer.write_all(xhtml_page(language, &book_title, &body).as_bytes())?;
+        }
+        for (file_name, content) in &pages {
+            writer.start_file(format!("OEBPS/text/{}", file_name), deflated)?;
+            writer.write_all(content.as_bytes())?;
+        }
+        writer.finish()?;
+    }
+    std::fs::rename(&temp, output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::io::Read;
+
+    fn titles(sections: &[Section]) -> Vec<(Option<&str>, usize)> {
+        sections
+            .iter()
+            .map(|x| (x.title.as_deref(), x.page))
+            .collect()
+    }
+
+    #[test]
+    fn sections_split_on_newpage_and_chapter() {
+        let text = "序\r\n[newpage]\n[chapter:第一章 [[rb:漢字 > かんじ]]]\n本文1\n\
+                    [chapter:第二章]本文2[newpage]本文3";
+        let sections = split_sections(text);
+        assert_eq!(
+            titles(&sections),
+            vec![
+                (None, 1),
+                // 紧跟在 [newpage] 后的章节与该页合并
+                (Some("第一章 [[rb:漢字 > かんじ]]"), 2),
+                (Some("第二章"), 2),
+                (None, 3),
+            ]
+        );
+        assert_eq!(sections[0].text, "序\n");
+        assert_eq!(sections[1].text, "\n\n本文1\n");
+        assert_eq!(sections[3].text, "本文3");
+    }
+
+    #[test]
+    fn unclosed_chapter_is_plain_text() {
+        let sections = split_sections("本文[chapter:未闭合");
+        assert_eq!(titles(&sections), vec![(None, 1)]);
+        assert_eq!(sections[0].text, "本文[chapter:未闭合");
+        assert_eq!(titles(&split_sections("")), vec![(None, 1)]);
+    }
+
+    #[test]
+    fn inline_markup() {
+        let jump = |page: usize| {
+            if page == 2 {
+                Some("p2.xhtml".to_string())
+            } else {
+                None
+            }
+        };
+        assert_eq!(
+            inline_html("[[rb:漢字 > かんじ]]<&>", &jump),
+            "<ruby>漢字<rp>(</rp><rt>かんじ</rt><rp>)</rp></ruby>&lt;&amp;&gt;"
+        );
+        assert_eq!(
+            inline_html("[[jumpuri:リンク > https://example.com/?a=1&b=2]]", &jump),
+            "<a href=\"https://example.com/?a=1&amp;b=2\">リンク</a>"
+        );
+        assert_eq!(
+            inline_html("[[jumpuri:危険 > javascript:alert(1)]]", &jump),
+            "危険"
+        );
+        assert_eq!(
+            inline_html("[jump:2][jump:9]", &jump),
+            "<a href=\"p2.xhtml\">→ 2</a>"
+        );
+        assert_eq!(
+            inline_html("[pixivimage:123-2][uploadedimage:9]", &jump),
+            "<a href=\"https://www.pixiv.net/artworks/123\">pixiv 123</a>"
+        );
+        // 不完整的标记原样输出
+        assert_eq!(inline_html("[[rb:漢字", &jump), "[[rb:漢字");
+    }
+
+    #[test]
+    fn export_single_novel() {
+        let novel = Novel::try_from(&serde_json::json!({
+            "id" : "1",
+            "title" : "タイトル",
+            "content" : "本文[newpage][chapter:第二章]続き[jump:1]",
+            "language" : "ja",
+            "userName" : "作者",
+        }))
+        .unwrap();
+        let output = std::env::temp_dir().join(format!("pixiv-epub-{}.epub", std::process::id()));
+        export_epub(&[novel], None, None, &output).unwrap();
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&output).unwrap()).unwrap();
+        {
+            // mimetype 必须是第一个文件且不压缩
+            let mimetype = archive.by_index(0).unwrap();
+            assert_eq!(mimetype.name(), "mimetype");
+            assert_eq!(mimetype.compression(), zip::CompressionMethod::Stored);
+        }
+        let mut read = |name: &str| {
+            let mut text = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            text
+        };
+        let nav = read("OEBPS/nav.xhtml");
+        assert!(nav.contains("<a href=\"text/novel001_001.xhtml\">タイトル</a>"));
+        assert!(nav.contains("<a href=\"text/novel001_002.xhtml\">第二章</a>"));
+        let page = read("OEBPS/text/novel001_002.xhtml");
+        assert!(page.contains("<h2>第二章</h2>"));
+        assert!(page.contains("<a href=\"novel001_001.xhtml\">→ 1</a>"));
+        std::fs::remove_file(&output).unwrap();
+    }
+}
@@ -1,8 +1,42 @@
+mod account_pool;
+/// `JSON_GET!` 在 artwork 中定义, 其他模型 (novel 等) 共用
+#[macro_use]
 mod artwork;
 mod artwork_db;
+mod cassette;
+mod cbz;
+mod embed;
+mod epub;
+mod novel;
+mod phash;
 mod pixiv_client;
-pub use artwork::{Artwork, PixivUser};
+mod proxy_pool;
+mod ranking;
+mod rate_limiter;
+mod retry;
+mod series;
+mod sidecar;
+mod thumbnail;
+mod transport;
+mod ugoira;
+mod user_profile;
+pub use account_pool::{AccountConfig, AccountPool, AccountUsage};
+pub use artwork::{Artwork, ArtworkType, PixivFile, PixivUser, UgoiraFrame};
+pub use cassette::CassetteMode;
+pub use cbz::export_cbz;
+pub use embed::{embed_metadata, EmbedError, EmbeddedMetadata};
+pub use epub::export_epub;
+pub use novel::Novel;
+pub use phash::{cluster_hashes, dhash, hash_from_hex, hash_to_hex};
 pub use pixiv_client::{PixivClient, PixivClientOption};
+pub use proxy_pool::{ProxyPool, ProxyPoolConfig};
+pub use rate_limiter::{RateLimitConfig, RateLimiter};
+pub use retry::RetryPolicy;
+pub use series::{PixivSeries, PixivSeriesItem};
+pub use sidecar::{write_sidecar, SidecarFormat};
+pub use thumbnail::{generate_thumbnails, ThumbnailError, ThumbnailFormat};
+pub use ugoira::{convert_ugoira, frames_from_zip, AnimationFormat};
+pub use user_profile::PixivUserProfile;
 
 #[derive(thiserror::Error, Debug)]
 pub enum PixivError {
@@ -12,10 +46,78 @@ pub enum PixivError {
     ClientIoError(#[from] std::io::Error),
     #[error("{0:?} 作品不存在或被删除")]
     ArtworkNotExists(i64),
+    #[error("{0:?} 小说不存在或被删除")]
+    NovelNotExists(i64),
+    #[error("{0:?} 系列不存在或被删除")]
+    SeriesNotExists(i64),
+    #[error("{0:?} 用户不存在或已退会")]
+    UserNotExists(i64),
     #[error("BadResponse {0}")]
     BadResponse(String, Vec<u8>),
     #[error("({0}) - JSON格式错误 : {1}")]
     ParseJSONError(String, String),
     #[error("({0}) - HTTP状态码错误 : {1}")]
     WrongHttpStatusCode(String, u16),
+    #[error("({0}) - 解压失败 : {1}")]
+    Decompression(String, String),
+    #[error("({0}) - 登录已失效,请更新Cookie")]
+    SessionExpired(String),
+    #[error("没有可用的账号,额度已用完或登录均已失效")]
+    NoAccountAvailable,
+}
+
+fn is_retryable_io(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::TimedOut
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::ConnectionRefused
+            | std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::UnexpectedEof
+            | std::io::ErrorKind::Interrupted
+    )
+}
+
+impl PixivError {
+    /// 重试后有可能成功的错误: 超时、连接异常和 429/5xx
+    ///
+    /// 证书、TLS、配置错误等重试也不会成功, 直接返回
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            PixivError::ClientError(e) => match e {
+                isahc::Error::Timeout
+                | isahc::Error::ConnectFailed
+                | isahc::Error::CouldntResolveHost
+                | isahc::Error::NoResponse
+                | isahc::Error::ResponseBodyError(_) => true,
+                isahc::Error::Io(e) => is_retryable_io(e),
+                _ => false,
+            },
+            PixivError::ClientIoError(e) => is_retryable_io(e),
+            PixivError::WrongHttpStatusCode(_, code) => RetryPolicy::is_retryable_status(*code),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_transient_errors_are_retryable() {
+        assert!(PixivError::ClientError(isahc::Error::Timeout).is_retryable());
+        assert!(PixivError::ClientError(isahc::Error::ConnectFailed).is_retryable());
+        assert!(!PixivError::ClientError(isahc::Error::SSLConnectFailed(None)).is_retryable());
+        assert!(!PixivError::ClientError(isahc::Error::BadServerCertificate(None)).is_retryable());
+        assert!(!PixivError::ClientError(isahc::Error::InvalidCredentials).is_retryable());
+        let reset = std::io::Error::from(std::io::ErrorKind::ConnectionReset);
+        assert!(PixivError::ClientIoError(reset).is_retryable());
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert!(!PixivError::ClientIoError(denied).is_retryable());
+        assert!(PixivError::WrongHttpStatusCode("x".into(), 502).is_retryable());
+        assert!(!PixivError::WrongHttpStatusCode("x".into(), 404).is_retryable());
+        assert!(!PixivError::ArtworkNotExists(1).is_retryable());
+    }
 }
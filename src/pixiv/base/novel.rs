@@ -0,0 +1,184 @@
+use super::artwork::{FromError, PixivSeriesNav, PixivTag, PixivUser};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// 小说, 字段与 `Artwork` 对应, 存放在单独的集合中
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Novel {
+    #[serde(
+        rename(serialize = "_id", deserialize = "_id"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub _id: Option<ObjectId>,
+    #[serde(rename(serialize = "id", deserialize = "id"))]
+    pub novel_id: i64,
+    #[serde(
+        rename(serialize = "title", deserialize = "title"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub title: Option<String>,
+    #[serde(
+        rename(serialize = "caption", deserialize = "caption"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub caption: Option<String>,
+    #[serde(
+        rename(serialize = "create_date", deserialize = "create_date"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub create_date: Option<String>,
+    /// 正文, 保留 pixiv 的 `[newpage]` `[chapter:]` 等标记
+    #[serde(
+        rename(serialize = "text", deserialize = "text"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub text: Option<String>,
+    #[serde(
+        rename(serialize = "word_count", deserialize = "word_count"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub word_count: Option<i32>,
+    #[serde(
+        rename(serialize = "character_count", deserialize = "character_count"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub character_count: Option<i32>,
+    #[serde(
+        rename(serialize = "cover_url", deserialize = "cover_url"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cover_url: Option<String>,
+    #[serde(
+        rename(serialize = "language", deserialize = "language"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub language: Option<String>,
+    #[serde(
+        rename(serialize = "series", deserialize = "series"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub series: Option<PixivSeriesNav>,
+    #[serde(
+        rename(serialize = "x_restrict", deserialize = "x_restrict"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub x_restrict: Option<i32>,
+    #[serde(
+        rename(serialize = "total_bookmarks", deserialize = "total_bookmarks"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub total_bookmarks: Option<i32>,
+    #[serde(
+        rename(serialize = "total_view", deserialize = "total_view"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub total_view: Option<i32>,
+    #[serde(
+        rename(serialize = "last_update_time", deserialize = "last_update_time"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_update_time: Option<i64>,
+    #[serde(
+        rename(serialize = "user", deserialize = "user"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub user: Option<PixivUser>,
+    #[serde(
+        rename(serialize = "tags", deserialize = "tags"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub tags: Option<Vec<PixivTag>>,
+}
+
+impl TryFrom<&serde_json::Value> for Novel {
+    type Error = FromError;
+
+    /// 对应小说详情页 preload 中的 `novel.{id}`
+    fn try_from(value: &serde_json::Value) -> Result<Self, Self::Error> {
+        let novel_id = match JSON_GET!(value, "id", as_str, |x| x.parse::<i64>()) {
+            Some(Ok(x)) => x,
+            Some(Err(_)) => return Err(FromError("ID类型错误".to_string())),
+            None => return Err(FromError("novel_id must be exists".to_string())),
+        };
+        // 不属于系列时 seriesNavData 为 null
+        let series = match value.get("seriesNavData") {
+            Some(x) if !x.is_null() => PixivSeriesNav::try_from(x).ok(),
+            _ => None,
+        };
+        let tags = value
+            .get("tags")
+            .and_then(|x| x.get("tags"))
+            .and_then(|x| x.as_array())
+            .map(|array| {
+                array
+                    .iter()
+                    .filter_map(|x| PixivTag::try_from(x).ok())
+                    .collect()
+            });
+        Ok(Novel {
+            _id: None,
+            novel_id,
+            title: JSON_GET!(value, "title", as_str, |x| x.to_string()),
+            caption: JSON_GET!(value, "description", as_str, |x| x.to_string()),
+            create_date: JSON_GET!(value, "createDate", as_str, |x| x.to_string()),
+            text: JSON_GET!(value, "content", as_str, |x| x.to_string()),
+            word_count: JSON_GET!(value, "wordCount", as_i64, |x| x as i32),
+            character_count: JSON_GET!(value, "characterCount", as_i64, |x| x as i32),
+            cover_url: JSON_GET!(value, "coverUrl", as_str, |x| x.to_string()),
+            language: JSON_GET!(value, "language", as_str, |x| x.to_string()),
+            series,
+            x_restrict: JSON_GET!(value, "xRestrict", as_i64, |x| x as i32),
+            total_bookmarks: JSON_GET!(value, "bookmarkCount", as_i64, |x| x as i32),
+            total_view: JSON_GET!(value, "viewCount", as_i64, |x| x as i32),
+            last_update_time: None,
+            user: PixivUser::try_from(value).ok(),
+            tags,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn novel_from_preload() {
+        let value = serde_json::json!({
+            "id": "13000001",
+            "title": "小説",
+            "description": "あらすじ",
+            "createDate": "2020-12-01T00:00:00+00:00",
+            "content": "本文1[newpage]本文2",
+            "wordCount": 6,
+            "characterCount": 12,
+            "coverUrl": "https://i.pximg.net/c/600x600/novel-cover-master/img/2020/12/01/00/00/00/13000001_cover.jpg",
+            "language": "ja",
+            "xRestrict": 0,
+            "bookmarkCount": 10,
+            "viewCount": 100,
+            "userId": "11",
+            "userName": "pixiv事務局",
+            "tags": {"tags": [{"tag": "オリジナル", "translation": {"en": "original"}}]},
+            "seriesNavData": null,
+        });
+        let novel = Novel::try_from(&value).unwrap();
+        assert_eq!(novel.novel_id, 13000001);
+        assert_eq!(novel.text.as_deref(), Some("本文1[newpage]本文2"));
+        assert_eq!(novel.caption.as_deref(), Some("あらすじ"));
+        assert_eq!(novel.character_count, Some(12));
+        assert_eq!(novel.user.unwrap().user_id, Some(11));
+        assert_eq!(novel.tags.unwrap()[0].trans.as_deref(), Some("original"));
+        assert!(novel.series.is_none());
+
+        let mut value = value;
+        value["seriesNavData"] =
+            serde_json::json!({"seriesId": 1200001, "title": "シリーズ", "order": 2});
+        let series = Novel::try_from(&value).unwrap().series.unwrap();
+        assert_eq!(series.series_id, 1200001);
+        assert_eq!(series.order, Some(2));
+
+        assert!(Novel::try_from(&serde_json::json!({"id": "abc"})).is_err());
+        assert!(Novel::try_from(&serde_json::json!({"title": "小説"})).is_err());
+    }
+}
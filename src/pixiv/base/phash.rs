@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// 差值哈希 (dHash): 缩放到 9x8 灰度图, 每行相邻像素比较亮度得到 64 位
+///
+/// 重新压缩/缩放过的同一张图哈希值只差几位, 用汉明距离判断是否重复
+pub fn dhash(path: &Path) -> Result<u64, image::ImageError> {
+    let image = image::open(path)?;
+    Ok(dhash_image(&image))
+}
+
+pub fn dhash_image(image: &image::DynamicImage) -> u64 {
+    let gray = image
+        .resize_exact(9, 8, image::imageops::FilterType::Triangle)
+        .to_luma8();
+    let mut hash: u64 = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if gray.get_pixel(x, y)[0] < gray.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// 哈希值以 16 位十六进制字符串存入数据库, bson 不支持 u64
+pub fn hash_to_hex(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+pub fn hash_from_hex(hex: &str) -> Option<u64> {
+    u64::from_str_radix(hex, 16).ok()
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// 以汉明距离为度量的 BK 树, 用于快速查找距离不超过阈值的哈希
+struct BkNode {
+    hash: u64,
+    items: Vec<usize>,
+    children: HashMap<u32, BkNode>,
+}
+
+impl BkNode {
+    fn insert(&mut self, hash: u64, item: usize) {
+        let distance = hamming_distance(self.hash, hash);
+        if distance == 0 {
+            self.items.push(item);
+            return;
+        }
+        match self.children.get_mut(&distance) {
+            Some(child) => child.insert(hash, item),
+            None => {
+                self.children.insert(
+                    distance,
+                    BkNode {
+                        hash,
+                        items: vec![item],
+                        children: HashMap::new(),
+                    },
+                );
+            }
+        }
+    }
+
+    fn find(&self, hash: u64, max_distance: u32, result: &mut Vec<usize>) {
+        let distance = hamming_distance(self.hash, hash);
+        if distance <= max_distance {
+            result.extend_from_slice(&self.items);
+        }
+        let low = distance.saturating_sub(max_distance);
+        let high = distance + max_distance;
+        for (d, child) in &self.children {
+            if *d >= low && *d <= high {
+                child.find(hash, max_distance, result);
+            }
+        }
+    }
+}
+
+fn find_root(parent: &mut [usize], x: usize) -> usize {
+    let mut root = x;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    let mut x = x;
+    while parent[x] != root {
+        let next = parent[x];
+        parent[x] = root;
+        x = next;
+    }
+    root
+}
+
+/// 把距离不超过 `max_distance` 的哈希归为一组 (传递闭包), 只返回至少两个成员的分组
+///
+/// 返回值是 `hashes` 中的下标, 组内按下标排序
+pub fn cluster_hashes(hashes: &[u64], max_distance: u32) -> Vec<Vec<usize>> {
+    let mut root: Option<BkNode> = None;
+    for (index, &hash) in hashes.iter().enumerate() {
+        match root {
+            Some(ref mut x) => x.insert(hash, index),
+            None => {
+                root = Some(BkNode {
+                    hash,
+                    items: vec![index],
+                    children: HashMap::new(),
+                })
+            }
+        }
+    }
+    let root = match root {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let mut parent: Vec<usize> = (0..hashes.len()).collect();
+    let mut neighbours = Vec::new();
+    for (index, &hash) in hashes.iter().enumerate() {
+        neighbours.clear();
+        root.find(hash, max_distance, &mut neighbours);
+        for &other in &neighbours {
+            let a = find_root(&mut parent, index);
+            let b = find_root(&mut parent, other);
+            if a != b {
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    let mut groups: Vec<Vec<usize>> = vec![Vec::new(); hashes.len()];
+    for index in 0..hashes.len() {
+        let r = find_root(&mut parent, index);
+        groups[r].push(index);
+    }
+    let mut clusters: Vec<Vec<usize>> = groups.into_iter().filter(|x| x.len() > 1).collect();
+    clusters.sort_by_key(|x| x[0]);
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        for &x in &[0u64, 1, u64::MAX, 0x0123_4567_89ab_cdef] {
+            assert_eq!(hash_from_hex(&hash_to_hex(x)), Some(x));
+        }
+        assert_eq!(hash_to_hex(1), "0000000000000001");
+        assert_eq!(hash_from_hex("xyz"), None);
+    }
+
+    #[test]
+    fn clusters_are_transitive() {
+        let hashes = [0b0000u64, 0b0011, 0b1111, 0xFFFF_0000, 0b0001];
+        assert_eq!(hamming_distance(hashes[0], hashes[2]), 4);
+        // 0-1-2 通过 1 相连, 4 与 0 相连, 3 单独一组不输出
+        assert_eq!(cluster_hashes(&hashes, 2), vec![vec![0, 1, 2, 4]]);
+        assert!(cluster_hashes(&hashes, 0).is_empty());
+    }
+
+    #[test]
+    fn dhash_of_image_files() {
+        let dir = std::env::temp_dir().join(format!("pixiv-phash-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // 从左到右变亮的渐变, 每行相邻像素都是右边更亮
+        let gradient = image::RgbImage::from_fn(180, 160, |x, y| {
+            let v = (x + y / 8) as u8;
+            image::Rgb([v, v, v])
+        });
+        let png = dir.join("gradient.png");
+        gradient.save(&png).unwrap();
+        assert_eq!(dhash(&png).unwrap(), u64::MAX);
+
+        let mirrored = image::imageops::flip_horizontal(&gradient);
+        let mirrored_png = dir.join("mirrored.png");
+        mirrored.save(&mirrored_png).unwrap();
+        assert_eq!(dhash(&mirrored_png).unwrap(), 0);
+
+        // 缩小并重新压缩为 jpg 后仍然接近
+        let small =
+            image::imageops::resize(&gradient, 90, 80, image::imageops::FilterType::Triangle);
+        let jpg = dir.join("small.jpg");
+        small.save(&jpg).unwrap();
+        assert!(hamming_distance(dhash(&jpg).unwrap(), u64::MAX) <= 2);
+
+        assert!(dhash(&dir.join("missing.png")).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
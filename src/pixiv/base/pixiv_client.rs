@@ -1,13 +1,25 @@
+use super::account_pool::AccountPool;
+use super::cassette::{CassetteMode, RecordingTransport, ReplayTransport};
+use super::transport::{IsahcTransport, Transport, TransportResponse};
+use super::ranking::RankingPage;
+use super::artwork::{PixivImageUrls, PixivUser, UgoiraMeta};
+use super::novel::Novel;
+use super::series::PixivSeries;
+use super::user_profile::PixivUserProfile;
 use super::Artwork;
 use super::PixivError;
-use log::debug;
+use super::proxy_pool::{ProxyPool, ProxyTransport};
+use super::rate_limiter::{Endpoint, RateLimiter};
+use super::retry::RetryPolicy;
+use log::{debug, warn};
 use std::convert::TryFrom;
 use isahc::prelude::*;
 use futures::AsyncReadExt;
 
 type Result<T> = std::result::Result<T, PixivError>;
 
-macro_rules! JSON_GET {
+/// 取接口返回中必需的字段, 不存在时返回 `ParseJSONError`; 模型中的可选字段用 artwork 中的 `JSON_GET!`
+macro_rules! JSON_FIELD {
     ($x : expr,$v: expr,$cookie : expr) => {
         match $x.get($v) {
             Some(x) => x,
@@ -27,6 +39,12 @@ pub struct PixivClientOption {
     _cookie: String,
     _ua: String,
     _country: String,
+    _cassette: Option<(CassetteMode, std::path::PathBuf)>,
+    _host: String,
+    _retry: RetryPolicy,
+    _rate_limiter: Option<std::sync::Arc<RateLimiter>>,
+    _proxy_pool: Option<std::sync::Arc<ProxyPool>>,
+    _account_pool: Option<std::sync::Arc<AccountPool>>,
 }
 
 impl PixivClientOption {
@@ -37,6 +55,12 @@ impl PixivClientOption {
             _cookie: "".into(),
             _ua: "".into(),
             _country: "CN".into(),
+            _cassette: None,
+            _host: "https://www.pixiv.net".into(),
+            _retry: RetryPolicy::default(),
+            _rate_limiter: None,
+            _proxy_pool: None,
+            _account_pool: None,
         }
     }
     pub fn proxy(mut self, proxy: &str) -> PixivClientOption {
@@ -55,11 +79,139 @@ impl PixivClientOption {
         self._ua = ua.into();
         self
     }
+    /// pixiv 站点地址, 集成测试时可以指向本地的 mock 服务
+    pub fn host(mut self, host: &str) -> PixivClientOption {
+        self._host = host.trim_end_matches('/').into();
+        self
+    }
+    pub fn retry(mut self, policy: RetryPolicy) -> PixivClientOption {
+        self._retry = policy;
+        self
+    }
+    /// 设置后忽略 `proxy`, 请求按代理池的模式分配到各个代理
+    pub fn proxy_pool(mut self, pool: std::sync::Arc<ProxyPool>) -> PixivClientOption {
+        self._proxy_pool = Some(pool);
+        self
+    }
+    /// 设置后忽略 `cookie`, 需要登录态的请求轮流使用账号池中的账号
+    pub fn account_pool(mut self, pool: std::sync::Arc<AccountPool>) -> PixivClientOption {
+        self._account_pool = Some(pool);
+        self
+    }
+    /// 多个客户端共用同一个限速器时, 总请求速率受限于限速器的配置
+    pub fn rate_limiter(mut self, limiter: std::sync::Arc<RateLimiter>) -> PixivClientOption {
+        self._rate_limiter = Some(limiter);
+        self
+    }
+    /// 录制模式下把所有请求写入 `dir`, 回放模式下只从 `dir` 读取, 不访问网络
+    pub fn cassette(mut self, mode: CassetteMode, dir: &str) -> PixivClientOption {
+        self._cassette = Some((mode, dir.into()));
+        self
+    }
+}
+
+/// 会话 Cookie 容器, 初始值来自 `PixivClientOption::cookie`, 之后随响应的 `Set-Cookie` 更新
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    cookies: Vec<(String, String)>,
+}
+
+impl CookieJar {
+    pub fn new() -> CookieJar {
+        CookieJar {
+            cookies: Vec::new(),
+        }
+    }
+    /// 解析 `a=1; b=2` 形式的 Cookie 字符串
+    pub fn parse(raw: &str) -> CookieJar {
+        let mut jar = CookieJar::new();
+        for pair in raw.split(';') {
+            let pair = pair.trim();
+            if pair.is_empty() {
+                continue;
+            }
+            match pair.find('=') {
+                Some(pos) => jar.set(pair[..pos].trim(), pair[pos + 1..].trim()),
+                None => continue,
+            }
+        }
+        jar
+    }
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookies
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.cookies.iter_mut().find(|(k, _)| k == name) {
+            Some(x) => x.1 = value.to_string(),
+            None => self.cookies.push((name.to_string(), value.to_string())),
+        }
+    }
+    pub fn remove(&mut self, name: &str) {
+        self.cookies.retain(|(k, _)| k != name);
+    }
+    /// 应用一条 `Set-Cookie` 响应头, 过期或 `Max-Age=0` 的 Cookie 会被删除
+    pub fn update_from_set_cookie(&mut self, header: &str) {
+        let mut parts = header.split(';');
+        let pair = match parts.next() {
+            Some(x) => x.trim(),
+            None => return,
+        };
+        let (name, value) = match pair.find('=') {
+            Some(pos) => (pair[..pos].trim(), pair[pos + 1..].trim()),
+            None => return,
+        };
+        if name.is_empty() {
+            return;
+        }
+        let mut expired = value.is_empty() || value == "deleted";
+        for attr in parts {
+            let attr = attr.trim();
+            let lower = attr.to_ascii_lowercase();
+            if lower.starts_with("max-age=") {
+                if let Ok(age) = attr[8..].trim().parse::<i64>() {
+                    expired = expired || age <= 0;
+                }
+            } else if lower.starts_with("expires=") {
+                if let Ok(date) = chrono::DateTime::parse_from_rfc2822(attr[8..].trim()) {
+                    expired = expired || date.timestamp() <= chrono::Utc::now().timestamp();
+                }
+            }
+        }
+        if expired {
+            self.remove(name);
+        } else {
+            self.set(name, value);
+        }
+    }
+    /// pixiv 的登录态保存在 `PHPSESSID` 中
+    pub fn has_session(&self) -> bool {
+        self.get("PHPSESSID").map_or(false, |x| !x.is_empty())
+    }
+    pub fn header_value(&self) -> Option<String> {
+        if self.cookies.is_empty() {
+            return None;
+        }
+        Some(
+            self.cookies
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<String>>()
+                .join("; "),
+        )
+    }
 }
 
 pub struct PixivClient {
-    _client: isahc::HttpClient,
+    _transport: Box<dyn Transport>,
     _options: PixivClientOption,
+    _cookies: CookieJar,
+    /// 创建时配置的 Cookie 是否带有登录态, 之后即使服务端删除了 `PHPSESSID` 也保持不变
+    _session_configured: bool,
+    /// 最近一次请求使用的账号 (账号池中的下标)
+    _last_account: Option<usize>,
 }
 
 fn parse_detail_page(content: &str) -> Option<String> {
@@ -70,24 +222,140 @@ fn parse_detail_page(content: &str) -> Option<String> {
     Some(content[start_pos..end_pos].to_string())
 }
 
-fn decompress_gzip(data: &[u8]) -> Option<Vec<u8>> {
-    // use std::io::Read;
-    // let mut decompressor = flate2::read::GzDecoder::new(data);
-    // let mut buffer: Vec<u8> = Vec::new();
-    // decompressor
-    //     .read_to_end(&mut buffer)
-    //     .map_or(None, |x| Some(x))?;
-    Some(data.to_vec())
+/// 页面中的 `meta-global-data` 含有当前登录用户信息, 未登录时 `userData` 为 null
+fn parse_global_data(content: &str) -> Option<String> {
+    let start_flag = "<meta name=\"global-data\" id=\"meta-global-data\" content='";
+    let end_flag = "'>";
+    let start_pos = content.find(start_flag)? + start_flag.len();
+    let end_pos = content[start_pos..].find(end_flag)? + start_pos;
+    Some(content[start_pos..end_pos].to_string())
+}
+
+fn is_logged_out(content: &str) -> bool {
+    let global_data = match parse_global_data(content) {
+        Some(x) => x,
+        None => return false,
+    };
+    match serde_json::from_str::<serde_json::Value>(&global_data) {
+        Ok(x) => x.get("userData").map_or(true, |u| u.is_null()),
+        Err(_) => false,
+    }
+}
+
+/// 按 `Content-Encoding` 解压响应体, 多重编码按声明的逆序依次解开
+fn decompress_body(url: &str, content_encoding: Option<&str>, data: Vec<u8>) -> Result<Vec<u8>> {
+    use std::io::Read;
+    let encodings = match content_encoding {
+        Some(x) => x
+            .split(',')
+            .map(|e| e.trim().to_ascii_lowercase())
+            .filter(|e| !e.is_empty())
+            .collect::<Vec<String>>(),
+        None => return Ok(data),
+    };
+    let mut data = data;
+    for encoding in encodings.iter().rev() {
+        let mut buffer: Vec<u8> = Vec::new();
+        let result = match encoding.as_str() {
+            "identity" => continue,
+            "gzip" | "x-gzip" => flate2::read::GzDecoder::new(&data[..]).read_to_end(&mut buffer),
+            "deflate" => {
+                // 规范要求 zlib 封装, 但部分服务端直接返回原始 deflate 数据
+                match flate2::read::ZlibDecoder::new(&data[..]).read_to_end(&mut buffer) {
+                    Ok(x) => Ok(x),
+                    Err(_) => {
+                        buffer.clear();
+                        flate2::read::DeflateDecoder::new(&data[..]).read_to_end(&mut buffer)
+                    }
+                }
+            }
+            "br" => brotli::Decompressor::new(&data[..], 4096).read_to_end(&mut buffer),
+            _ => {
+                return Err(PixivError::Decompression(
+                    url.to_string(),
+                    format!("不支持的编码 {}", encoding),
+                ))
+            }
+        };
+        if let Err(e) = result {
+            return Err(PixivError::Decompression(
+                url.to_string(),
+                format!("{} : {}", encoding, e),
+            ));
+        }
+        data = buffer;
+    }
+    Ok(data)
+}
+
+fn append_extension(path: &std::path::Path, ext: &str) -> std::path::PathBuf {
+    let mut x = path.as_os_str().to_owned();
+    x.push(".");
+    x.push(ext);
+    x.into()
+}
+
+/// 解析 `Content-Range: bytes 100-199/200` 中的起始位置
+fn parse_content_range_start(value: &str) -> Option<u64> {
+    let range = value.trim().strip_prefix("bytes")?.trim();
+    range.split('-').next()?.trim().parse::<u64>().ok()
+}
+
+async fn read_body(url: &str, response: &mut TransportResponse) -> Result<Vec<u8>> {
+    let mut bytes_content = Vec::new();
+    response.body.read_to_end(&mut bytes_content).await?;
+    let content_encoding = response
+        .headers
+        .get(http::header::CONTENT_ENCODING)
+        .and_then(|x| x.to_str().ok());
+    decompress_body(url, content_encoding, bytes_content)
+}
+
+/// 把响应体追加写入 `file`, 返回本次写入的字节数; 不足 `Content-Length` 时视为中断
+async fn write_body(
+    url: &str,
+    resp: &mut TransportResponse,
+    file: &mut async_std::fs::File,
+    content_length: Option<u64>,
+) -> Result<u64> {
+    use futures::AsyncWriteExt;
+    let mut written: u64 = 0;
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = resp.body.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buffer[..n]).await?;
+        written += n as u64;
+    }
+    file.flush().await?;
+    file.sync_all().await?;
+    if let Some(expected) = content_length {
+        if written != expected {
+            return Err(PixivError::ClientIoError(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("{} 下载不完整: {}/{} 字节", url, written, expected),
+            )));
+        }
+    }
+    Ok(written)
+}
+
+async fn read_text(url: &str, response: &mut TransportResponse) -> Result<String> {
+    let content = read_body(url, response).await?;
+    match String::from_utf8(content) {
+        Ok(x) => Ok(x),
+        Err(e) => Err(PixivError::BadResponse(url.to_string(), e.into_bytes())),
+    }
 }
 
 impl PixivClient {
-    pub fn new_with_option(option: PixivClientOption) -> Result<PixivClient> {
+    fn build_http_client(option: &PixivClientOption, proxy: Option<&str>) -> Result<isahc::HttpClient> {
         let mut c = isahc::HttpClientBuilder::new();
         c = c.timeout(std::time::Duration::from_secs(10));
+        c = c.automatic_decompression(false);
         c = c.ssl_options(isahc::config::SslOption::DANGER_ACCEPT_INVALID_CERTS | isahc::config::SslOption::DANGER_ACCEPT_INVALID_HOSTS);
-        
-
-        let mut default_headers = http::HeaderMap::new();
         c = c.default_header("sec-fetch-dest","empty");
         c = c.default_header("sec-fetch-mode","cors");
         c = c.default_header("sec-fetch-site","none");
@@ -98,37 +366,202 @@ impl PixivClient {
         ));
         c = c.default_header("accept","text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.9");
         c = c.default_header("user-agent",option._ua.clone());
-        match option._proxy {
-            Some(ref x) => c = c.proxy(Some(x.parse().unwrap())),
+        match proxy {
+            Some(x) => c = c.proxy(Some(x.parse().unwrap())),
             _ => (),
         };
-        Ok(PixivClient {
-            _client: c.build()?,
+        Ok(c.build()?)
+    }
+
+    pub fn new_with_option(option: PixivClientOption) -> Result<PixivClient> {
+        let network: Box<dyn Transport> = match option._proxy_pool {
+            Some(ref pool) => {
+                let mut clients = Vec::new();
+                for index in 0..pool.len() {
+                    clients.push(PixivClient::build_http_client(&option, Some(pool.uri(index)))?);
+                }
+                Box::new(ProxyTransport::new(pool.clone(), clients))
+            }
+            None => Box::new(IsahcTransport::new(PixivClient::build_http_client(
+                &option,
+                option._proxy.as_deref(),
+            )?)),
+        };
+        let transport: Box<dyn Transport> = match option._cassette {
+            Some((CassetteMode::Replay, ref dir)) => Box::new(ReplayTransport::new(dir.clone())),
+            Some((CassetteMode::Record, ref dir)) => {
+                Box::new(RecordingTransport::new(network, dir.clone()))
+            }
+            None => network,
+        };
+        Ok(PixivClient::new_with_transport(option, transport))
+    }
+
+    /// 使用自定义的传输层创建客户端, 例如离线测试用的 `FixtureTransport`
+    pub fn new_with_transport(option: PixivClientOption, transport: Box<dyn Transport>) -> PixivClient {
+        let cookies = CookieJar::parse(&option._cookie);
+        PixivClient {
+            _transport: transport,
             _options: option,
-        })
+            _session_configured: cookies.has_session(),
+            _cookies: cookies,
+            _last_account: None,
+        }
+    }
+
+    pub fn cookies(&self) -> &CookieJar {
+        &self._cookies
+    }
+
+    /// 发送请求, 附带会话 Cookie 并记录响应中的 `Set-Cookie`
+    ///
+    /// 网络错误和 429/5xx 会按 `RetryPolicy` 重试, 重试用尽后返回最后一次的结果
+    async fn send(
+        &mut self,
+        request: http::Request<()>,
+        with_cookie: bool,
+        endpoint: Endpoint,
+    ) -> Result<TransportResponse> {
+        let (parts, _) = request.into_parts();
+        let url = parts.uri.to_string();
+        let max_attempts = self._options._retry.max_attempts.max(1);
+        let mut attempt: u32 = 0;
+        loop {
+            attempt += 1;
+            let mut request = http::Request::builder()
+                .method(parts.method.clone())
+                .uri(parts.uri.clone())
+                .body(())
+                .unwrap();
+            *request.headers_mut() = parts.headers.clone();
+            let mut account = None;
+            if with_cookie {
+                let cookie = match self._options._account_pool {
+                    Some(ref pool) => {
+                        let index = match pool.acquire() {
+                            Some(x) => x,
+                            None => return Err(PixivError::NoAccountAvailable),
+                        };
+                        account = Some(index);
+                        if let Some(ua) = pool.user_agent(index) {
+                            if let Ok(x) = http::HeaderValue::from_str(ua) {
+                                request.headers_mut().insert(http::header::USER_AGENT, x);
+                            }
+                        }
+                        pool.cookie_header(index)
+                    }
+                    None => self._cookies.header_value(),
+                };
+                if let Some(cookie) = cookie {
+                    match http::HeaderValue::from_str(&cookie) {
+                        Ok(x) => {
+                            request.headers_mut().insert(http::header::COOKIE, x);
+                        }
+                        Err(_) => debug!("Cookie 含有非法字符, 已忽略"),
+                    }
+                }
+            }
+            self._last_account = account;
+            if let Some(ref limiter) = self._options._rate_limiter {
+                limiter.acquire(endpoint).await;
+            }
+            let (delay, reason) = match self._transport.send(request).await {
+                Ok(response) => {
+                    for value in response.headers.get_all(http::header::SET_COOKIE) {
+                        if let Ok(x) = value.to_str() {
+                            match (&self._options._account_pool, account) {
+                                (Some(pool), Some(index)) => pool.update_cookies(index, x),
+                                _ => self._cookies.update_from_set_cookie(x),
+                            }
+                        }
+                    }
+                    if response.status == 429 {
+                        if let Some(ref limiter) = self._options._rate_limiter {
+                            limiter.throttled(endpoint);
+                        }
+                    }
+                    if !RetryPolicy::is_retryable_status(response.status) {
+                        if attempt > 1 {
+                            debug!("[{}] 第 {} 次请求成功", url, attempt);
+                        }
+                        return Ok(response);
+                    }
+                    if attempt >= max_attempts {
+                        warn!(
+                            "[{}] 状态码 {} , 已尝试 {} 次, 放弃重试",
+                            url, response.status, attempt
+                        );
+                        return Ok(response);
+                    }
+                    let delay = response
+                        .headers
+                        .get(http::header::RETRY_AFTER)
+                        .and_then(|x| x.to_str().ok())
+                        .and_then(|x| self._options._retry.retry_after(x))
+                        .unwrap_or(self._options._retry.backoff(attempt));
+                    (delay, format!("状态码 {}", response.status))
+                }
+                Err(e) => {
+                    if !e.is_retryable() {
+                        return Err(e);
+                    }
+                    if attempt >= max_attempts {
+                        warn!("[{}] {} , 已尝试 {} 次, 放弃重试", url, e, attempt);
+                        return Err(e);
+                    }
+                    (self._options._retry.backoff(attempt), e.to_string())
+                }
+            };
+            warn!(
+                "[{}] 第 {}/{} 次请求失败 ({}), {} 毫秒后重试",
+                url,
+                attempt,
+                max_attempts,
+                reason,
+                delay.as_millis()
+            );
+            async_std::task::sleep(delay).await;
+        }
+    }
+
+    /// 最近一次请求所用的 Cookie 是否配置过登录态
+    ///
+    /// 服务端可能通过 `Set-Cookie` 删除 `PHPSESSID`, 所以不能只看当前 Cookie 中是否还有会话
+    fn session_configured(&self) -> bool {
+        match (&self._options._account_pool, self._last_account) {
+            (Some(pool), Some(index)) => pool.session_configured(index),
+            _ => self._session_configured,
+        }
+    }
+
+    /// 最近一次请求所用的账号登录已失效, 从账号池中移除
+    fn retire_session(&self) {
+        if let (Some(pool), Some(index)) = (&self._options._account_pool, self._last_account) {
+            pool.retire(index);
+        }
+    }
+
+    async fn get(&mut self, url: &str, endpoint: Endpoint) -> Result<TransportResponse> {
+        let request = http::Request::get(url).body(()).unwrap();
+        self.send(request, true, endpoint).await
     }
 
     pub async fn load_artwork(&mut self, pixiv_id: i64) -> Result<Artwork> {
         let error_cookie = format!("load_artwork-{}", pixiv_id);
         let url = format!(
-            "https://www.pixiv.net/artworks/{}?lang={}",
-            pixiv_id, self._options._language
+            "{}/artworks/{}?lang={}",
+            self._options._host, pixiv_id, self._options._language
         );
-        let mut response = self._client.get_async(&url).await?;
-        let status_code = response.status().as_u16();
+        let mut response = self.get(&url, Endpoint::Artwork).await?;
+        let status_code = response.status;
         match status_code {
             200 => {
-                let mut bytes_content = Vec::new();
-                response.body_mut().read_to_end(&mut bytes_content).await?;
-                let content = match decompress_gzip(&bytes_content) {
-                    Some(x) => x,
-                    None => return Err(PixivError::BadResponse(url, bytes_content)),
-                };
-                let content = if let Ok(x) = String::from_utf8(content) {
-                    x
-                } else {
-                    return Err(PixivError::BadResponse(url, bytes_content));
-                };
+                let content = read_text(&url, &mut response).await?;
+
+                if self.session_configured() && is_logged_out(&content) {
+                    self.retire_session();
+                    return Err(PixivError::SessionExpired(error_cookie));
+                }
 
                 let parse_result = parse_detail_page(&content).map_or(
                     Err(PixivError::BadResponse(url, content.as_bytes().to_vec())),
@@ -139,8 +572,8 @@ impl PixivClient {
                     Ok(x) => x,
                     Err(_) => return Err(PixivError::ParseJSONError(error_cookie, parse_result)),
                 };
-                let illust_value = JSON_GET!(&json_value, "illust", error_cookie);
-                let illust_value = JSON_GET!(illust_value, &format!("{}", pixiv_id), error_cookie);
+                let illust_value = JSON_FIELD!(&json_value, "illust", error_cookie);
+                let illust_value = JSON_FIELD!(illust_value, &format!("{}", pixiv_id), error_cookie);
                 let artwork = match Artwork::try_from(illust_value) {
                     Ok(x) => x,
                     Err(e) => {
@@ -154,36 +587,109 @@ impl PixivClient {
         }
     }
 
+    /// 多页作品 (漫画/插画集) 每一页的图片地址, 按页码顺序
+    pub async fn load_pages(&mut self, artwork_id: i64) -> Result<Vec<PixivImageUrls>> {
+        let error_cookie = format!("load_pages-{}", artwork_id);
+        let url = format!(
+            "{}/ajax/illust/{}/pages?lang={}",
+            self._options._host, artwork_id, self._options._language
+        );
+        let mut response = self.get(&url, Endpoint::Artwork).await?;
+        let status_code = response.status;
+        match status_code {
+            200 => (),
+            404 => return Err(PixivError::ArtworkNotExists(artwork_id)),
+            _ => return Err(PixivError::WrongHttpStatusCode(error_cookie, status_code)),
+        }
+        let content = read_text(&url, &mut response).await?;
+        let json_value: serde_json::Value = match serde_json::from_str(&content) {
+            Ok(x) => x,
+            Err(_) => return Err(PixivError::ParseJSONError(error_cookie, content)),
+        };
+        let pages_json = JSON_FIELD!(&json_value, "body", error_cookie);
+        let pages_json = match pages_json.as_array() {
+            Some(x) => x,
+            None => {
+                return Err(PixivError::ParseJSONError(
+                    error_cookie,
+                    "body类型错误".to_string(),
+                ))
+            }
+        };
+        let mut pages = Vec::new();
+        for page in pages_json {
+            let urls = JSON_FIELD!(page, "urls", error_cookie);
+            match PixivImageUrls::try_from_page(urls) {
+                Ok(x) => pages.push(x),
+                Err(e) => return Err(PixivError::ParseJSONError(error_cookie, e.0)),
+            }
+        }
+        Ok(pages)
+    }
+
+    /// 动图的帧压缩包地址和每一帧的显示时间
+    pub async fn load_ugoira_meta(&mut self, artwork_id: i64) -> Result<UgoiraMeta> {
+        let error_cookie = format!("load_ugoira_meta-{}", artwork_id);
+        let url = format!(
+            "{}/ajax/illust/{}/ugoira_meta?lang={}",
+            self._options._host, artwork_id, self._options._language
+        );
+        let mut response = self.get(&url, Endpoint::Artwork).await?;
+        let status_code = response.status;
+        match status_code {
+            200 => (),
+            404 => return Err(PixivError::ArtworkNotExists(artwork_id)),
+            _ => return Err(PixivError::WrongHttpStatusCode(error_cookie, status_code)),
+        }
+        let content = read_text(&url, &mut response).await?;
+        let json_value: serde_json::Value = match serde_json::from_str(&content) {
+            Ok(x) => x,
+            Err(_) => return Err(PixivError::ParseJSONError(error_cookie, content)),
+        };
+        let body = JSON_FIELD!(&json_value, "body", error_cookie);
+        match UgoiraMeta::try_from(body) {
+            Ok(x) => Ok(x),
+            Err(e) => Err(PixivError::ParseJSONError(error_cookie, e.0)),
+        }
+    }
+
+    /// 下载动图的帧压缩包, 优先使用原尺寸
+    pub async fn download_ugoira(&mut self, meta: &UgoiraMeta) -> Result<Vec<u8>> {
+        let url = match meta.original_src.as_ref().or(meta.src.as_ref()) {
+            Some(x) => x.clone(),
+            None => {
+                return Err(PixivError::ParseJSONError(
+                    "download_ugoira".to_string(),
+                    "缺少压缩包地址".to_string(),
+                ))
+            }
+        };
+        self.download_image(&url).await
+    }
+
     pub async fn load_by_creator(&mut self, creator_id: i64) -> Result<Vec<i64>> {
         let mut result = Vec::new();
-        let url = format!("https://www.pixiv.net/ajax/user/{}/profile/all", creator_id);
+        let url = format!(
+            "{}/ajax/user/{}/profile/all",
+            self._options._host, creator_id
+        );
         let error_cookie = format!("load_by_creator_{}", creator_id);
-        let mut response = self._client.get_async(&url).await?;
-        let status_code = response.status().as_u16();
+        let mut response = self.get(&url, Endpoint::Profile).await?;
+        let status_code = response.status;
         if status_code != 200 {
             return Err(PixivError::WrongHttpStatusCode(error_cookie, status_code));
         }
-        let mut bytes_content = Vec::new();
-        response.body_mut().read_to_end(&mut bytes_content).await?;
-        let content = match decompress_gzip(&bytes_content) {
-            Some(x) => x,
-            None => return Err(PixivError::BadResponse(url, bytes_content)),
-        };
-        let content = if let Ok(x) = String::from_utf8(content) {
-            x
-        } else {
-            return Err(PixivError::BadResponse(url, bytes_content));
-        };
+        let content = read_text(&url, &mut response).await?;
         let json_value: serde_json::Value = match serde_json::from_str(&content) {
             Ok(x) => x,
             Err(_) => return Err(PixivError::ParseJSONError(error_cookie, content)),
         };
-        let body = JSON_GET!(&json_value, "body", error_cookie);
+        let body = JSON_FIELD!(&json_value, "body", error_cookie);
         let empty_map: serde_json::Map<String, serde_json::Value> = serde_json::Map::new();
-        let illusts = JSON_GET!(body, "illusts", error_cookie)
+        let illusts = JSON_FIELD!(body, "illusts", error_cookie)
             .as_object()
             .unwrap_or(&empty_map);
-        let manga = JSON_GET!(body, "manga", error_cookie)
+        let manga = JSON_FIELD!(body, "manga", error_cookie)
             .as_object()
             .unwrap_or(&empty_map);
         for key in illusts.keys() {
@@ -208,21 +714,351 @@ impl PixivClient {
         }
         Ok(result)
     }
+    /// 作者资料, 用户不存在或已退会时返回 `UserNotExists`
+    pub async fn load_user(&mut self, user_id: i64) -> Result<PixivUserProfile> {
+        let error_cookie = format!("load_user-{}", user_id);
+        let url = format!(
+            "{}/ajax/user/{}?full=1&lang={}",
+            self._options._host, user_id, self._options._language
+        );
+        let mut response = self.get(&url, Endpoint::Profile).await?;
+        let status_code = response.status;
+        match status_code {
+            200 => (),
+            404 => return Err(PixivError::UserNotExists(user_id)),
+            _ => return Err(PixivError::WrongHttpStatusCode(error_cookie, status_code)),
+        }
+        let content = read_text(&url, &mut response).await?;
+        let json_value: serde_json::Value = match serde_json::from_str(&content) {
+            Ok(x) => x,
+            Err(_) => return Err(PixivError::ParseJSONError(error_cookie, content)),
+        };
+        let body = JSON_FIELD!(&json_value, "body", error_cookie);
+        match PixivUserProfile::try_from(body) {
+            Ok(x) => Ok(x),
+            Err(e) => Err(PixivError::ParseJSONError(error_cookie, e.0)),
+        }
+    }
+
+    /// 当前登录账号的用户id, 来自首页 `meta-global-data` 的 `userData`
+    pub async fn load_self_id(&mut self) -> Result<i64> {
+        let error_cookie = "load_self_id".to_string();
+        let url = format!("{}/?lang={}", self._options._host, self._options._language);
+        let mut response = self.get(&url, Endpoint::Profile).await?;
+        let status_code = response.status;
+        if status_code != 200 {
+            return Err(PixivError::WrongHttpStatusCode(error_cookie, status_code));
+        }
+        let content = read_text(&url, &mut response).await?;
+        let global_data = match parse_global_data(&content) {
+            Some(x) => x,
+            None => return Err(PixivError::BadResponse(url, content.into_bytes())),
+        };
+        let json_value: serde_json::Value = match serde_json::from_str(&global_data) {
+            Ok(x) => x,
+            Err(_) => return Err(PixivError::ParseJSONError(error_cookie, global_data)),
+        };
+        let user_data = JSON_FIELD!(&json_value, "userData", error_cookie);
+        if user_data.is_null() {
+            self.retire_session();
+            return Err(PixivError::SessionExpired(error_cookie));
+        }
+        match JSON_FIELD!(user_data, "id", error_cookie)
+            .as_str()
+            .and_then(|x| x.parse::<i64>().ok())
+        {
+            Some(x) => Ok(x),
+            None => Err(PixivError::ParseJSONError(
+                error_cookie,
+                "userData.id类型错误".to_string(),
+            )),
+        }
+    }
+
+    /// 用户的关注列表, 返回 (关注总数, 这一页的作者)
+    ///
+    /// `rest` 为 `show` (公开关注) 或 `hide` (非公开关注, 只能查看当前登录账号自己的)
+    pub async fn following(
+        &mut self,
+        user_id: i64,
+        offset: u32,
+        limit: u32,
+        rest: &str,
+    ) -> Result<(u32, Vec<PixivUser>)> {
+        let error_cookie = format!("following-{}-{}-{}-{}", user_id, offset, limit, rest);
+        let url = format!(
+            "{}/ajax/user/{}/following?offset={}&limit={}&rest={}&tag=&lang={}",
+            self._options._host, user_id, offset, limit, rest, self._options._language
+        );
+        let mut response = self.get(&url, Endpoint::Profile).await?;
+        let status_code = response.status;
+        match status_code {
+            200 => (),
+            404 => return Err(PixivError::UserNotExists(user_id)),
+            _ => return Err(PixivError::WrongHttpStatusCode(error_cookie, status_code)),
+        }
+        let content = read_text(&url, &mut response).await?;
+        let json_value: serde_json::Value = match serde_json::from_str(&content) {
+            Ok(x) => x,
+            Err(_) => return Err(PixivError::ParseJSONError(error_cookie, content)),
+        };
+        let body = JSON_FIELD!(&json_value, "body", error_cookie);
+        let total = JSON_FIELD!(body, "total", error_cookie).as_u64().unwrap_or(0) as u32;
+        let users = match JSON_FIELD!(body, "users", error_cookie).as_array() {
+            Some(x) => x,
+            None => {
+                return Err(PixivError::ParseJSONError(
+                    error_cookie,
+                    "users类型错误".to_string(),
+                ))
+            }
+        };
+        let users = users
+            .iter()
+            .filter_map(|x| PixivUser::try_from(x).ok())
+            .filter(|x| x.user_id.is_some())
+            .collect();
+        Ok((total, users))
+    }
+
+    /// 漫画/插画系列的一页 (每页 12 话), 返回的 `items` 只包含这一页, `page` 从 1 开始
+    pub async fn load_series(&mut self, series_id: i64, page: u32) -> Result<PixivSeries> {
+        let error_cookie = format!("load_series-{}-{}", series_id, page);
+        let url = format!(
+            "{}/ajax/series/{}?p={}&lang={}",
+            self._options._host, series_id, page, self._options._language
+        );
+        let mut response = self.get(&url, Endpoint::Artwork).await?;
+        let status_code = response.status;
+        match status_code {
+            200 => (),
+            // 系列不存在时返回 400/404
+            400 | 404 => return Err(PixivError::SeriesNotExists(series_id)),
+            _ => return Err(PixivError::WrongHttpStatusCode(error_cookie, status_code)),
+        }
+        let content = read_text(&url, &mut response).await?;
+        let json_value: serde_json::Value = match serde_json::from_str(&content) {
+            Ok(x) => x,
+            Err(_) => return Err(PixivError::ParseJSONError(error_cookie, content)),
+        };
+        let body = JSON_FIELD!(&json_value, "body", error_cookie);
+        match PixivSeries::try_from((series_id, body)) {
+            Ok(x) => Ok(x),
+            Err(e) => Err(PixivError::ParseJSONError(error_cookie, e.0)),
+        }
+    }
+
+    /// 小说详情, 正文在详情页 preload 的 `novel.{id}.content` 中
+    pub async fn load_novel(&mut self, novel_id: i64) -> Result<Novel> {
+        let error_cookie = format!("load_novel-{}", novel_id);
+        let url = format!(
+            "{}/novel/show.php?id={}&lang={}",
+            self._options._host, novel_id, self._options._language
+        );
+        let mut response = self.get(&url, Endpoint::Artwork).await?;
+        let status_code = response.status;
+        match status_code {
+            200 => (),
+            404 => return Err(PixivError::NovelNotExists(novel_id)),
+            _ => return Err(PixivError::WrongHttpStatusCode(error_cookie, status_code)),
+        }
+        let content = read_text(&url, &mut response).await?;
+        if self.session_configured() && is_logged_out(&content) {
+            self.retire_session();
+            return Err(PixivError::SessionExpired(error_cookie));
+        }
+        let parse_result = match parse_detail_page(&content) {
+            Some(x) => x,
+            None => return Err(PixivError::BadResponse(url, content.into_bytes())),
+        };
+        let json_value: serde_json::Value = match serde_json::from_str(&parse_result) {
+            Ok(x) => x,
+            Err(_) => return Err(PixivError::ParseJSONError(error_cookie, parse_result)),
+        };
+        let novel_value = JSON_FIELD!(&json_value, "novel", error_cookie);
+        let novel_value = JSON_FIELD!(novel_value, &format!("{}", novel_id), error_cookie);
+        match Novel::try_from(novel_value) {
+            Ok(x) => Ok(x),
+            Err(e) => Err(PixivError::ParseJSONError(error_cookie, e.0)),
+        }
+    }
+
+    /// 作者的所有小说id, 来自 `profile/all` 的 `novels`
+    pub async fn load_novels_by_creator(&mut self, creator_id: i64) -> Result<Vec<i64>> {
+        let url = format!(
+            "{}/ajax/user/{}/profile/all",
+            self._options._host, creator_id
+        );
+        let error_cookie = format!("load_novels_by_creator_{}", creator_id);
+        let mut response = self.get(&url, Endpoint::Profile).await?;
+        let status_code = response.status;
+        if status_code != 200 {
+            return Err(PixivError::WrongHttpStatusCode(error_cookie, status_code));
+        }
+        let content = read_text(&url, &mut response).await?;
+        let json_value: serde_json::Value = match serde_json::from_str(&content) {
+            Ok(x) => x,
+            Err(_) => return Err(PixivError::ParseJSONError(error_cookie, content)),
+        };
+        let body = JSON_FIELD!(&json_value, "body", error_cookie);
+        // 没有小说时 novels 是空数组而不是对象
+        let novels = match body.get("novels").and_then(|x| x.as_object()) {
+            Some(x) => x,
+            None => return Ok(Vec::new()),
+        };
+        let mut result = Vec::new();
+        for key in novels.keys() {
+            match key.parse::<i64>() {
+                Ok(x) => result.push(x),
+                Err(_) => debug!("[{}] 类型错误: {}", error_cookie, key),
+            }
+        }
+        Ok(result)
+    }
+
     pub async fn download_image(&mut self,url : &str) -> Result<Vec<u8>> {
-        let referer = "https://www.pixiv.net/".to_string();
+        let referer = format!("{}/", self._options._host);
         let req = isahc::http::Request::builder()
             .header("Referer",referer)
             .uri(url)
             .method(isahc::http::Method::GET)
             .body(()).unwrap();
-        let mut resp = self._client.send_async(req).await?;
-        let status_code = resp.status().as_u16();
+        let mut resp = self.send(req, false, Endpoint::Image).await?;
+        let status_code = resp.status;
         if status_code != 200 {
             return Err(PixivError::WrongHttpStatusCode("(download_img)".to_string(), status_code));
         }
-        let mut bytes_content = Vec::new();
-        resp.body_mut().read_to_end(&mut bytes_content).await?;
-        Ok(bytes_content)
+        read_body(url, &mut resp).await
+    }
+
+    /// 流式下载到 `path`, 先写入 `path.part`, 完整后再改名
+    ///
+    /// 上次中断留下的 `.part` 会用 `Range`/`If-Range` 续传, 资源变化时服务端返回 200 则从头下载;
+    /// 校验器 (ETag 或 Last-Modified) 保存在 `path.part.validator` 中。返回文件的总字节数
+    pub async fn download_image_to(&mut self, url: &str, path: &std::path::Path) -> Result<u64> {
+        let error_cookie = format!("(download_img_to-{})", url);
+        let part_path = append_extension(path, "part");
+        let validator_path = append_extension(path, "part.validator");
+        if let Some(parent) = path.parent() {
+            async_std::fs::create_dir_all(parent).await?;
+        }
+        let max_attempts = self._options._retry.max_attempts.max(1);
+        let mut attempt: u32 = 0;
+        loop {
+            attempt += 1;
+            // 请求阶段的错误 send 已经按 RetryPolicy 重试过, 这里只重试读取响应体时的中断
+            match self
+                .download_part(url, &part_path, &validator_path, &error_cookie)
+                .await?
+            {
+                Ok(total) => {
+                    async_std::fs::rename(&part_path, path).await?;
+                    let _ = async_std::fs::remove_file(&validator_path).await;
+                    return Ok(total);
+                }
+                // 传输中断时 .part 已保留, 下一次从断点续传
+                Err(e) if e.is_retryable() && attempt < max_attempts => {
+                    warn!(
+                        "[{}] 第 {}/{} 次下载中断 ({}), 稍后续传",
+                        url, attempt, max_attempts, e
+                    );
+                    async_std::task::sleep(self._options._retry.backoff(attempt)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// 发起一次 (续传) 请求并写入 `.part`
+    ///
+    /// 外层错误来自请求阶段, 内层错误来自读取响应体, 只有后者需要续传
+    async fn download_part(
+        &mut self,
+        url: &str,
+        part_path: &std::path::Path,
+        validator_path: &std::path::Path,
+        error_cookie: &str,
+    ) -> Result<Result<u64>> {
+        let existing = match async_std::fs::metadata(part_path).await {
+            Ok(x) => x.len(),
+            Err(_) => 0,
+        };
+        let validator = async_std::fs::read_to_string(validator_path).await.ok();
+        let mut builder = http::Request::builder()
+            .header("Referer", format!("{}/", self._options._host))
+            .header(http::header::ACCEPT_ENCODING, "identity")
+            .uri(url)
+            .method(http::Method::GET);
+        let resume = existing > 0 && validator.is_some();
+        if resume {
+            builder = builder
+                .header(http::header::RANGE, format!("bytes={}-", existing))
+                .header(http::header::IF_RANGE, validator.as_ref().unwrap().trim());
+        }
+        let mut resp = self
+            .send(builder.body(()).unwrap(), false, Endpoint::Image)
+            .await?;
+        let content_length = resp
+            .headers
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.parse::<u64>().ok());
+        let (mut file, start) = match resp.status {
+            206 if resume => {
+                let range_start = resp
+                    .headers
+                    .get(http::header::CONTENT_RANGE)
+                    .and_then(|x| x.to_str().ok())
+                    .and_then(parse_content_range_start);
+                // 与断点对不上的 .part 无法续传, 和 416 一样丢弃
+                if range_start != Some(existing) {
+                    let _ = async_std::fs::remove_file(part_path).await;
+                    let _ = async_std::fs::remove_file(validator_path).await;
+                    return Err(PixivError::BadResponse(
+                        format!("{} Content-Range 与断点 {} 不一致", url, existing),
+                        Vec::new(),
+                    ));
+                }
+                let file = async_std::fs::OpenOptions::new()
+                    .append(true)
+                    .open(part_path)
+                    .await?;
+                (file, existing)
+            }
+            200 => {
+                let validator = resp
+                    .headers
+                    .get(http::header::ETAG)
+                    .or(resp.headers.get(http::header::LAST_MODIFIED))
+                    .and_then(|x| x.to_str().ok())
+                    .map(|x| x.to_string());
+                match validator {
+                    Some(x) => async_std::fs::write(validator_path, x).await?,
+                    None => {
+                        let _ = async_std::fs::remove_file(validator_path).await;
+                    }
+                }
+                (async_std::fs::File::create(part_path).await?, 0)
+            }
+            // 断点超出文件大小, 丢弃 .part 下次从头下载
+            416 => {
+                let _ = async_std::fs::remove_file(part_path).await;
+                let _ = async_std::fs::remove_file(validator_path).await;
+                return Err(PixivError::WrongHttpStatusCode(
+                    error_cookie.to_string(),
+                    416,
+                ));
+            }
+            status_code => {
+                return Err(PixivError::WrongHttpStatusCode(
+                    error_cookie.to_string(),
+                    status_code,
+                ))
+            }
+        };
+        Ok(write_body(url, &mut resp, &mut file, content_length)
+            .await
+            .map(|written| start + written))
     }
     pub async fn search(
         &mut self,
@@ -233,36 +1069,26 @@ impl PixivClient {
     ) -> Result<Vec<i64>> {
         let error_cookie = format!("({}-{}-{}-{})", tag, sort, _type, page);
         let (url, json_key) = if _type == "manga" {
-            (format!("https://www.pixiv.net/ajax/search/manga/{}?word={0}&order={}&mode=all&p={}&s_mode=s_tag_full&type=illust_and_ugoira&lang=zh"
-                    ,urlencoding::encode(tag),sort,page),"manga")
+            (format!("{}/ajax/search/manga/{}?word={1}&order={}&mode=all&p={}&s_mode=s_tag_full&type=illust_and_ugoira&lang=zh"
+                    ,self._options._host,urlencoding::encode(tag),sort,page),"manga")
         } else {
-            (format!("https://www.pixiv.net/ajax/search/illustrations/{}?word={0}&order={}&mode=all&p={}&s_mode=s_tag_full&type=illust_and_ugoira&lang=zh"
-                    ,urlencoding::encode(tag),sort,page),"illust")
+            (format!("{}/ajax/search/illustrations/{}?word={1}&order={}&mode=all&p={}&s_mode=s_tag_full&type=illust_and_ugoira&lang=zh"
+                    ,self._options._host,urlencoding::encode(tag),sort,page),"illust")
         };
         // let url = format!("{}",urlencoding::encode(tag));
-        let mut response = self._client.get_async(&url).await?;
-        let status_code = response.status().as_u16();
+        let mut response = self.get(&url, Endpoint::Search).await?;
+        let status_code = response.status;
         if status_code != 200 {
             return Err(PixivError::WrongHttpStatusCode(error_cookie, status_code));
         }
-        let mut bytes_content = Vec::new();
-        response.body_mut().read_to_end(&mut bytes_content).await?;
-        let content = match decompress_gzip(&bytes_content) {
-            Some(x) => x,
-            None => return Err(PixivError::BadResponse(url, bytes_content)),
-        };
-        let content = if let Ok(x) = String::from_utf8(content) {
-            x
-        } else {
-            return Err(PixivError::BadResponse(url, bytes_content));
-        };
+        let content = read_text(&url, &mut response).await?;
         let json_value: serde_json::Value = match serde_json::from_str(&content) {
             Ok(x) => x,
             Err(_) => return Err(PixivError::ParseJSONError(error_cookie, content)),
         };
-        let illusts_json = JSON_GET!(&json_value, "body", error_cookie);
-        let illusts_json = JSON_GET!(&illusts_json, json_key, error_cookie);
-        let illusts_json = JSON_GET!(&illusts_json, "data", error_cookie);
+        let illusts_json = JSON_FIELD!(&json_value, "body", error_cookie);
+        let illusts_json = JSON_FIELD!(&illusts_json, json_key, error_cookie);
+        let illusts_json = JSON_FIELD!(&illusts_json, "data", error_cookie);
         let illusts_json = match illusts_json.as_array() {
             Some(x) => x,
             None => {
@@ -291,4 +1117,523 @@ impl PixivClient {
         }
         Ok(artworks)
     }
+
+    /// 按标签搜索小说, 每页 24 个
+    pub async fn search_novels(&mut self, tag: &str, sort: &str, page: u32) -> Result<Vec<i64>> {
+        let error_cookie = format!("(novel-{}-{}-{})", tag, sort, page);
+        let url = format!(
+            "{}/ajax/search/novels/{}?word={1}&order={}&mode=all&p={}&s_mode=s_tag_full&lang=zh",
+            self._options._host,
+            urlencoding::encode(tag),
+            sort,
+            page
+        );
+        let mut response = self.get(&url, Endpoint::Search).await?;
+        let status_code = response.status;
+        if status_code != 200 {
+            return Err(PixivError::WrongHttpStatusCode(error_cookie, status_code));
+        }
+        let content = read_text(&url, &mut response).await?;
+        let json_value: serde_json::Value = match serde_json::from_str(&content) {
+            Ok(x) => x,
+            Err(_) => return Err(PixivError::ParseJSONError(error_cookie, content)),
+        };
+        let novels_json = JSON_FIELD!(&json_value, "body", error_cookie);
+        let novels_json = JSON_FIELD!(&novels_json, "novel", error_cookie);
+        let novels_json = JSON_FIELD!(&novels_json, "data", error_cookie);
+        let novels_json = match novels_json.as_array() {
+            Some(x) => x,
+            None => {
+                return Err(PixivError::ParseJSONError(
+                    error_cookie,
+                    "body类型错误".to_string(),
+                ))
+            }
+        };
+        Ok(novels_json
+            .iter()
+            .filter_map(|x| x.get("id")?.as_str()?.parse::<i64>().ok())
+            .collect())
+    }
+
+    /// 排行榜, `mode` 为 daily/weekly/monthly/rookie/original/male/female 等,
+    /// `content` 为 all/illust/manga/ugoira, `date` 为 `20201201` 格式, None 表示最新一期
+    pub async fn ranking(
+        &mut self,
+        mode: &str,
+        content: &str,
+        date: Option<&str>,
+        page: u32,
+    ) -> Result<RankingPage> {
+        let error_cookie = format!("ranking-{}-{}-{:?}-{}", mode, content, date, page);
+        let mut url = format!(
+            "{}/ranking.php?mode={}&content={}&p={}&format=json",
+            self._options._host, mode, content, page
+        );
+        if let Some(date) = date {
+            url.push_str(&format!("&date={}", date));
+        }
+        let mut response = self.get(&url, Endpoint::Search).await?;
+        let status_code = response.status;
+        if status_code != 200 {
+            return Err(PixivError::WrongHttpStatusCode(error_cookie, status_code));
+        }
+        let content = read_text(&url, &mut response).await?;
+        let json_value: serde_json::Value = match serde_json::from_str(&content) {
+            Ok(x) => x,
+            Err(_) => return Err(PixivError::ParseJSONError(error_cookie, content)),
+        };
+        match RankingPage::try_from(&json_value) {
+            Ok(x) => Ok(x),
+            Err(e) => Err(PixivError::ParseJSONError(error_cookie, e.0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::transport::{Fixture, FixtureTransport};
+    use super::*;
+
+    const LOGGED_OUT_PAGE: &str = "<html><meta name=\"global-data\" id=\"meta-global-data\" content='{\"userData\":null}'></html>";
+
+    fn fixture_client(cookie: &str, transport: FixtureTransport) -> PixivClient {
+        let option = PixivClientOption::new()
+            .cookie(cookie)
+            .retry(RetryPolicy::none());
+        PixivClient::new_with_transport(option, Box::new(transport))
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut e = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    #[test]
+    fn decompress_by_content_encoding() {
+        use std::io::Write;
+        let text = b"{\"error\":false,\"body\":{}}".to_vec();
+        assert_eq!(decompress_body("u", None, text.clone()).unwrap(), text);
+        assert_eq!(
+            decompress_body("u", Some("identity"), text.clone()).unwrap(),
+            text
+        );
+        assert_eq!(
+            decompress_body("u", Some("GZIP"), gzip(&text)).unwrap(),
+            text
+        );
+
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(&text).unwrap();
+        assert_eq!(
+            decompress_body("u", Some("deflate"), zlib.finish().unwrap()).unwrap(),
+            text
+        );
+        // 不带 zlib 头的原始 deflate 数据
+        let mut raw =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        raw.write_all(&text).unwrap();
+        assert_eq!(
+            decompress_body("u", Some("deflate"), raw.finish().unwrap()).unwrap(),
+            text
+        );
+
+        let mut br = Vec::new();
+        {
+            let mut w = brotli::CompressorWriter::new(&mut br, 4096, 5, 22);
+            w.write_all(&text).unwrap();
+        }
+        assert_eq!(decompress_body("u", Some("br"), br.clone()).unwrap(), text);
+
+        // `Content-Encoding: gzip, br` 表示先 gzip 再 br, 解压时逆序
+        let mut both = Vec::new();
+        {
+            let mut w = brotli::CompressorWriter::new(&mut both, 4096, 5, 22);
+            w.write_all(&gzip(&text)).unwrap();
+        }
+        assert_eq!(decompress_body("u", Some("gzip, br"), both).unwrap(), text);
+    }
+
+    #[test]
+    fn decompress_errors_are_typed() {
+        match decompress_body("u", Some("gzip"), b"not gzip".to_vec()) {
+            Err(PixivError::Decompression(url, msg)) => {
+                assert_eq!(url, "u");
+                assert!(msg.starts_with("gzip"));
+            }
+            x => panic!("{:?}", x),
+        }
+        match decompress_body("u", Some("zstd"), Vec::new()) {
+            Err(PixivError::Decompression(_, msg)) => assert!(msg.contains("zstd")),
+            x => panic!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn gzip_response_through_transport() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(
+            "https://www.pixiv.net/artworks/1",
+            Fixture {
+                status: 200,
+                headers: vec![("content-encoding".into(), "gzip".into())],
+                body: gzip(LOGGED_OUT_PAGE.as_bytes()),
+            },
+        );
+        let mut client = fixture_client("PHPSESSID=1", transport);
+        match async_std::task::block_on(client.load_artwork(1)) {
+            Err(PixivError::SessionExpired(_)) => (),
+            x => panic!("{:?}", x.map(|x| x.artwork_id)),
+        }
+    }
+
+    #[test]
+    fn cookie_jar_parse() {
+        let jar = CookieJar::parse(" PHPSESSID=123_abc ; p_ab_id=1;;broken; a=b=c");
+        assert_eq!(jar.get("PHPSESSID"), Some("123_abc"));
+        assert_eq!(jar.get("p_ab_id"), Some("1"));
+        assert_eq!(jar.get("a"), Some("b=c"));
+        assert_eq!(jar.get("broken"), None);
+        assert!(jar.has_session());
+        assert_eq!(
+            jar.header_value().as_deref(),
+            Some("PHPSESSID=123_abc; p_ab_id=1; a=b=c")
+        );
+        assert_eq!(CookieJar::new().header_value(), None);
+    }
+
+    #[test]
+    fn cookie_jar_set_cookie_merge() {
+        let mut jar = CookieJar::parse("PHPSESSID=old; a=1");
+        jar.update_from_set_cookie("PHPSESSID=new; path=/; domain=.pixiv.net; HttpOnly");
+        jar.update_from_set_cookie("b=2; Max-Age=3600");
+        assert_eq!(
+            jar.header_value().as_deref(),
+            Some("PHPSESSID=new; a=1; b=2")
+        );
+        jar.update_from_set_cookie("a=1; Max-Age=0");
+        jar.update_from_set_cookie("b=2; expires=Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(jar.header_value().as_deref(), Some("PHPSESSID=new"));
+        jar.update_from_set_cookie("PHPSESSID=deleted");
+        assert!(!jar.has_session());
+        assert_eq!(jar.header_value(), None);
+    }
+
+    #[test]
+    fn set_cookie_from_response_is_kept() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(
+            "https://www.pixiv.net/artworks/1",
+            Fixture {
+                status: 404,
+                headers: vec![("set-cookie".into(), "p_ab_id=9; path=/".into())],
+                body: Vec::new(),
+            },
+        );
+        let mut client = fixture_client("PHPSESSID=1", transport);
+        match async_std::task::block_on(client.load_artwork(1)) {
+            Err(PixivError::ArtworkNotExists(1)) => (),
+            x => panic!("{:?}", x.map(|x| x.artwork_id)),
+        }
+        assert_eq!(client.cookies().get("p_ab_id"), Some("9"));
+    }
+
+    #[test]
+    fn logged_out_page_is_session_expired() {
+        let mut transport = FixtureTransport::new();
+        transport.insert_body("https://www.pixiv.net/artworks/1", 200, LOGGED_OUT_PAGE);
+        let mut client = fixture_client("PHPSESSID=1", transport);
+        match async_std::task::block_on(client.load_artwork(1)) {
+            Err(PixivError::SessionExpired(_)) => (),
+            x => panic!("{:?}", x.map(|x| x.artwork_id)),
+        }
+    }
+
+    #[test]
+    fn session_deleted_by_server_is_session_expired() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(
+            "https://www.pixiv.net/artworks/1",
+            Fixture {
+                status: 200,
+                headers: vec![("set-cookie".into(), "PHPSESSID=deleted; Max-Age=0".into())],
+                body: LOGGED_OUT_PAGE.into(),
+            },
+        );
+        let mut client = fixture_client("PHPSESSID=1", transport);
+        match async_std::task::block_on(client.load_artwork(1)) {
+            Err(PixivError::SessionExpired(_)) => (),
+            x => panic!("{:?}", x.map(|x| x.artwork_id)),
+        }
+        assert!(!client.cookies().has_session());
+    }
+
+    #[test]
+    fn logged_out_page_without_cookie_is_not_an_error() {
+        let mut transport = FixtureTransport::new();
+        transport.insert_body("https://www.pixiv.net/artworks/1", 200, LOGGED_OUT_PAGE);
+        let mut client = fixture_client("", transport);
+        match async_std::task::block_on(client.load_artwork(1)) {
+            Err(PixivError::BadResponse(_, _)) => (),
+            x => panic!("{:?}", x.map(|x| x.artwork_id)),
+        }
+    }
+
+    #[test]
+    fn load_pages_from_fixture() {
+        let pages: Vec<serde_json::Value> = (0..3)
+            .map(|n| {
+                serde_json::json!({
+                    "urls": {
+                        "thumb_mini": format!("https://i.pximg.net/c/128x128/img-master/img/2020/12/01/00/00/00/84000001_p{}_square1200.jpg", n),
+                        "regular": format!("https://i.pximg.net/img-master/img/2020/12/01/00/00/00/84000001_p{}_master1200.jpg", n),
+                        "original": format!("https://i.pximg.net/img-original/img/2020/12/01/00/00/00/84000001_p{}.png", n),
+                    },
+                    "width": 1200,
+                    "height": 1700,
+                })
+            })
+            .collect();
+        let body = serde_json::json!({"error": false, "message": "", "body": pages});
+        let mut transport = FixtureTransport::new();
+        transport.insert_body(
+            "https://www.pixiv.net/ajax/illust/84000001/pages",
+            200,
+            body.to_string(),
+        );
+        let mut client = fixture_client("", transport);
+        let pages = async_std::task::block_on(client.load_pages(84000001)).unwrap();
+        let originals: Vec<&str> = pages.iter().map(|x| x.large.as_deref().unwrap()).collect();
+        assert_eq!(originals.len(), 3);
+        for (n, url) in originals.iter().enumerate() {
+            assert!(url.ends_with(&format!("84000001_p{}.png", n)));
+        }
+        match async_std::task::block_on(client.load_pages(2)) {
+            Err(PixivError::ArtworkNotExists(2)) => (),
+            x => panic!("{:?}", x.map(|x| x.len())),
+        }
+    }
+
+    #[test]
+    fn load_artwork_from_preload_fixture() {
+        let preload = serde_json::json!({
+            "illust": {"84000001": {
+                "id": "84000001",
+                "title": "漫画",
+                "illustType": 1,
+                "pageCount": 3,
+                "userId": "11",
+                "urls": {"original": "https://i.pximg.net/img-original/img/2020/12/01/00/00/00/84000001_p0.png"},
+            }},
+        });
+        let page = format!(
+            "<html><meta name=\"preload-data\" id=\"meta-preload-data\" content='{}'></html>",
+            preload
+        );
+        let mut transport = FixtureTransport::new();
+        transport.insert_body("https://www.pixiv.net/artworks/84000001", 200, page);
+        let mut client = fixture_client("", transport);
+        let artwork = async_std::task::block_on(client.load_artwork(84000001)).unwrap();
+        assert_eq!(artwork.page_count, Some(3));
+        assert_eq!(artwork.user.unwrap().user_id, Some(11));
+    }
+
+    #[test]
+    fn load_series_from_fixture() {
+        let body = serde_json::json!({
+            "error": false,
+            "body": {
+                "illustSeries": [{"id": "120001", "title": "シリーズ", "total": 1}],
+                "page": {"series": [{"workId": "84000001", "order": 1}], "total": 1},
+            },
+        });
+        let mut transport = FixtureTransport::new();
+        transport.insert_body(
+            "https://www.pixiv.net/ajax/series/120001?p=1&lang=zh",
+            200,
+            body.to_string(),
+        );
+        transport.insert_body(
+            "https://www.pixiv.net/ajax/series/120002?p=1&lang=zh",
+            400,
+            "{\"error\":true}",
+        );
+        let mut client = fixture_client("", transport);
+        let series = async_std::task::block_on(client.load_series(120001, 1)).unwrap();
+        assert_eq!(series.items[0].artwork_id, 84000001);
+        match async_std::task::block_on(client.load_series(120002, 1)) {
+            Err(PixivError::SeriesNotExists(120002)) => (),
+            x => panic!("{:?}", x.map(|x| x.series_id)),
+        }
+    }
+
+    #[test]
+    fn load_novel_from_preload_fixture() {
+        let preload = serde_json::json!({
+            "novel": {"13000001": {
+                "id": "13000001",
+                "title": "小説",
+                "content": "本文",
+                "userId": "11",
+                "seriesNavData": null,
+            }},
+        });
+        let page = format!(
+            "<html><meta name=\"preload-data\" id=\"meta-preload-data\" content='{}'></html>",
+            preload
+        );
+        let mut transport = FixtureTransport::new();
+        transport.insert_body(
+            "https://www.pixiv.net/novel/show.php?id=13000001",
+            200,
+            page,
+        );
+        let mut client = fixture_client("", transport);
+        let novel = async_std::task::block_on(client.load_novel(13000001)).unwrap();
+        assert_eq!(novel.text.as_deref(), Some("本文"));
+        assert!(novel.series.is_none());
+    }
+
+    #[test]
+    fn load_ugoira_meta_from_fixture() {
+        let body = serde_json::json!({
+            "error": false,
+            "body": {
+                "src": "https://i.pximg.net/img-zip-ugoira/img/2020/12/01/00/00/00/84000001_ugoira600x600.zip",
+                "originalSrc": "https://i.pximg.net/img-zip-ugoira/img/2020/12/01/00/00/00/84000001_ugoira1920x1080.zip",
+                "mime_type": "image/jpeg",
+                "frames": [{"file": "000000.jpg", "delay": 40}, {"file": "000001.jpg", "delay": 60}],
+            },
+        });
+        let mut transport = FixtureTransport::new();
+        transport.insert_body(
+            "https://www.pixiv.net/ajax/illust/84000001/ugoira_meta",
+            200,
+            body.to_string(),
+        );
+        transport.insert_body(
+            "https://i.pximg.net/img-zip-ugoira/img/2020/12/01/00/00/00/84000001_ugoira1920x1080.zip",
+            200,
+            "zip",
+        );
+        let mut client = fixture_client("", transport);
+        let meta = async_std::task::block_on(client.load_ugoira_meta(84000001)).unwrap();
+        assert_eq!(meta.frames.len(), 2);
+        assert_eq!(meta.frames[1].delay, 60);
+        // 优先下载原尺寸的压缩包
+        let data = async_std::task::block_on(client.download_ugoira(&meta)).unwrap();
+        assert_eq!(data, b"zip");
+        match async_std::task::block_on(client.load_ugoira_meta(2)) {
+            Err(PixivError::ArtworkNotExists(2)) => (),
+            x => panic!("{:?}", x.map(|x| x.frames.len())),
+        }
+    }
+
+    const IMAGE_URL: &str =
+        "https://i.pximg.net/img-original/img/2020/12/01/00/00/00/84000001_p0.png";
+
+    /// 在临时目录里放好上次中断留下的 `.part` 和校验器, 再用 `fixture` 下载一次
+    fn download_with_part(
+        name: &str,
+        part: Option<(&str, &str)>,
+        fixture: Fixture,
+    ) -> (Result<u64>, std::path::PathBuf) {
+        let dir =
+            std::env::temp_dir().join(format!("pixiv-download-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("84000001_p0.png");
+        if let Some((data, validator)) = part {
+            std::fs::write(append_extension(&path, "part"), data).unwrap();
+            std::fs::write(append_extension(&path, "part.validator"), validator).unwrap();
+        }
+        let mut transport = FixtureTransport::new();
+        transport.insert(IMAGE_URL, fixture);
+        let mut client = fixture_client("", transport);
+        let result = async_std::task::block_on(client.download_image_to(IMAGE_URL, &path));
+        (result, path)
+    }
+
+    fn read(path: &std::path::Path, ext: &str) -> Option<String> {
+        std::fs::read_to_string(append_extension(path, ext)).ok()
+    }
+
+    #[test]
+    fn download_resumes_with_partial_content() {
+        let fixture = Fixture {
+            status: 206,
+            headers: vec![
+                ("Content-Range".into(), "bytes 3-5/6".into()),
+                ("Content-Length".into(), "3".into()),
+            ],
+            body: b"def".to_vec(),
+        };
+        let (result, path) = download_with_part("resume", Some(("abc", "\"v1\"")), fixture);
+        assert_eq!(result.unwrap(), 6);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "abcdef");
+        assert_eq!(read(&path, "part"), None);
+        assert_eq!(read(&path, "part.validator"), None);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn download_restarts_when_resource_changed() {
+        // 服务端返回 200 时从头写 .part 并换成新的校验器; 响应体不完整时保留下来等待续传
+        let fixture = Fixture {
+            status: 200,
+            headers: vec![
+                ("ETag".into(), "\"v2\"".into()),
+                ("Content-Length".into(), "6".into()),
+            ],
+            body: b"uvw".to_vec(),
+        };
+        let (result, path) = download_with_part("restart", Some(("abc", "\"v1\"")), fixture);
+        match result {
+            Err(PixivError::ClientIoError(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof)
+            }
+            x => panic!("{:?}", x),
+        }
+        assert!(!path.exists());
+        assert_eq!(read(&path, "part").as_deref(), Some("uvw"));
+        assert_eq!(read(&path, "part.validator").as_deref(), Some("\"v2\""));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn download_discards_part_on_416() {
+        let fixture = Fixture {
+            status: 416,
+            headers: Vec::new(),
+            body: Vec::new(),
+        };
+        let (result, path) = download_with_part("416", Some(("abc", "\"v1\"")), fixture);
+        match result {
+            Err(PixivError::WrongHttpStatusCode(_, 416)) => (),
+            x => panic!("{:?}", x),
+        }
+        assert_eq!(read(&path, "part"), None);
+        assert_eq!(read(&path, "part.validator"), None);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn download_discards_part_on_content_range_mismatch() {
+        let fixture = Fixture {
+            status: 206,
+            headers: vec![("Content-Range".into(), "bytes 0-5/6".into())],
+            body: b"abcdef".to_vec(),
+        };
+        let (result, path) = download_with_part("mismatch", Some(("abc", "\"v1\"")), fixture);
+        match result {
+            Err(PixivError::BadResponse(_, _)) => (),
+            x => panic!("{:?}", x),
+        }
+        assert!(!path.exists());
+        assert_eq!(read(&path, "part"), None);
+        assert_eq!(read(&path, "part.validator"), None);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
 }
@@ -0,0 +1,266 @@
+use super::transport::{ResponseBody, Transport, TransportFuture, TransportResponse};
+use log::{info, warn};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyMode {
+    /// 每个请求轮流使用下一个健康的代理
+    RoundRobin,
+    /// 每个客户端固定使用一个代理, 该代理不可用时才临时换用其他代理
+    Sticky,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ProxyPoolConfig {
+    pub proxies: Vec<String>,
+    #[serde(default = "default_mode")]
+    pub mode: ProxyMode,
+    /// 连续多少次网络错误后标记为不可用
+    #[serde(default = "default_max_failures")]
+    pub max_failures: u32,
+    #[serde(default = "default_check_url")]
+    pub check_url: String,
+    #[serde(default = "default_check_interval_secs")]
+    pub check_interval_secs: u64,
+}
+
+fn default_mode() -> ProxyMode {
+    ProxyMode::RoundRobin
+}
+fn default_max_failures() -> u32 {
+    3
+}
+fn default_check_url() -> String {
+    "https://www.pixiv.net/".to_string()
+}
+fn default_check_interval_secs() -> u64 {
+    60
+}
+
+struct ProxyState {
+    uri: String,
+    healthy: AtomicBool,
+    consecutive_failures: AtomicU32,
+    success: AtomicU64,
+    failure: AtomicU64,
+}
+
+/// 多个代理组成的代理池, 记录每个代理的成功/失败次数和健康状态
+pub struct ProxyPool {
+    proxies: Vec<ProxyState>,
+    cursor: AtomicUsize,
+    config: ProxyPoolConfig,
+}
+
+impl ProxyPool {
+    /// 代理列表为空时返回 None
+    pub fn new(config: ProxyPoolConfig) -> Option<ProxyPool> {
+        if config.proxies.is_empty() {
+            return None;
+        }
+        Some(ProxyPool {
+            proxies: config
+                .proxies
+                .iter()
+                .map(|x| ProxyState {
+                    uri: x.clone(),
+                    healthy: AtomicBool::new(true),
+                    consecutive_failures: AtomicU32::new(0),
+                    success: AtomicU64::new(0),
+                    failure: AtomicU64::new(0),
+                })
+                .collect(),
+            cursor: AtomicUsize::new(0),
+            config,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.proxies.len()
+    }
+
+    pub fn mode(&self) -> ProxyMode {
+        self.config.mode
+    }
+
+    pub fn uri(&self, index: usize) -> &str {
+        &self.proxies[index].uri
+    }
+
+    /// 轮询下一个健康的代理, 全部不可用时仍然轮询, 避免请求卡死
+    pub fn next_index(&self) -> usize {
+        let len = self.proxies.len();
+        for _ in 0..len {
+            let index = self.cursor.fetch_add(1, Ordering::Relaxed) % len;
+            if self.proxies[index].healthy.load(Ordering::Relaxed) {
+                return index;
+            }
+        }
+        self.cursor.fetch_add(1, Ordering::Relaxed) % len
+    }
+
+    pub fn select(&self, sticky: Option<usize>) -> usize {
+        match sticky {
+            Some(x) if self.proxies[x].healthy.load(Ordering::Relaxed) => x,
+            _ => self.next_index(),
+        }
+    }
+
+    pub fn report_success(&self, index: usize) {
+        let proxy = &self.proxies[index];
+        proxy.success.fetch_add(1, Ordering::Relaxed);
+        proxy.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+
+    pub fn report_failure(&self, index: usize) {
+        let proxy = &self.proxies[index];
+        proxy.failure.fetch_add(1, Ordering::Relaxed);
+        let failures = proxy.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.config.max_failures && proxy.healthy.swap(false, Ordering::Relaxed) {
+            warn!("代理 {} 连续失败 {} 次, 标记为不可用", proxy.uri, failures);
+        }
+    }
+
+    pub fn log_stats(&self) {
+        for proxy in &self.proxies {
+            info!(
+                "代理 {} [{}] 成功 {} 次, 失败 {} 次",
+                proxy.uri,
+                if proxy.healthy.load(Ordering::Relaxed) {
+                    "可用"
+                } else {
+                    "不可用"
+                },
+                proxy.success.load(Ordering::Relaxed),
+                proxy.failure.load(Ordering::Relaxed)
+            );
+        }
+    }
+
+    async fn probe(&self, index: usize) -> bool {
+        use isahc::prelude::*;
+        let proxy = match self.proxies[index].uri.parse::<http::Uri>() {
+            Ok(x) => x,
+            Err(_) => return false,
+        };
+        let client = match isahc::HttpClient::builder()
+            .timeout(Duration::from_secs(10))
+            .proxy(Some(proxy))
+            .build()
+        {
+            Ok(x) => x,
+            Err(_) => return false,
+        };
+        match client.head_async(self.config.check_url.as_str()).await {
+            Ok(x) => x.status().as_u16() < 500,
+            Err(_) => false,
+        }
+    }
+
+    /// 后台定时重新探测不可用的代理, 并输出各代理的计数
+    pub fn spawn_health_check(self: Arc<Self>) {
+        async_std::task::spawn(async move {
+            loop {
+                async_std::task::sleep(Duration::from_secs(self.config.check_interval_secs)).await;
+                for index in 0..self.proxies.len() {
+                    let proxy = &self.proxies[index];
+                    if proxy.healthy.load(Ordering::Relaxed) {
+                        continue;
+                    }
+                    if self.probe(index).await {
+                        proxy.consecutive_failures.store(0, Ordering::Relaxed);
+                        proxy.healthy.store(true, Ordering::Relaxed);
+                        info!("代理 {} 已恢复", proxy.uri);
+                    }
+                }
+                self.log_stats();
+            }
+        });
+    }
+}
+
+/// 通过代理池发送请求, 每个代理对应一个 HttpClient
+pub struct ProxyTransport {
+    pool: Arc<ProxyPool>,
+    clients: Vec<isahc::HttpClient>,
+    sticky: Option<usize>,
+}
+
+impl ProxyTransport {
+    /// `clients` 与代理池中的代理一一对应
+    pub fn new(pool: Arc<ProxyPool>, clients: Vec<isahc::HttpClient>) -> ProxyTransport {
+        let sticky = match pool.mode() {
+            ProxyMode::Sticky => Some(pool.next_index()),
+            ProxyMode::RoundRobin => None,
+        };
+        ProxyTransport {
+            pool,
+            clients,
+            sticky,
+        }
+    }
+}
+
+impl Transport for ProxyTransport {
+    fn send(&self, request: http::Request<()>) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let index = self.pool.select(self.sticky);
+            match self.clients[index].send_async(request).await {
+                Ok(response) => {
+                    self.pool.report_success(index);
+                    let (parts, body) = response.into_parts();
+                    Ok(TransportResponse {
+                        status: parts.status.as_u16(),
+                        headers: parts.headers,
+                        body: Box::new(body) as ResponseBody,
+                    })
+                }
+                Err(e) => {
+                    self.pool.report_failure(index);
+                    Err(e.into())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(proxies: &[&str]) -> Option<ProxyPool> {
+        ProxyPool::new(ProxyPoolConfig {
+            proxies: proxies.iter().map(|x| x.to_string()).collect(),
+            mode: ProxyMode::RoundRobin,
+            max_failures: 2,
+            check_url: default_check_url(),
+            check_interval_secs: default_check_interval_secs(),
+        })
+    }
+
+    #[test]
+    fn empty_pool_is_rejected() {
+        assert!(pool(&[]).is_none());
+    }
+
+    #[test]
+    fn round_robin_skips_unhealthy() {
+        let pool = pool(&["http://a", "http://b", "http://c"]).unwrap();
+        assert_eq!(
+            (0..4).map(|_| pool.next_index()).collect::<Vec<usize>>(),
+            vec![0, 1, 2, 0]
+        );
+        pool.report_failure(1);
+        pool.report_failure(1);
+        assert_eq!(
+            (0..4).map(|_| pool.next_index()).collect::<Vec<usize>>(),
+            vec![2, 0, 2, 0]
+        );
+        assert_eq!(pool.select(Some(1)), 2);
+        assert_eq!(pool.select(Some(0)), 0);
+    }
+}
@@ -0,0 +1,113 @@
+use super::artwork::FromError;
+use std::convert::TryFrom;
+
+#[derive(Debug)]
+pub struct RankingItem {
+    pub artwork_id: i64,
+    pub rank: i32,
+}
+
+/// `ranking.php?format=json` 的一页结果, 每页 50 个作品
+#[derive(Debug)]
+pub struct RankingPage {
+    pub mode: String,
+    pub content: String,
+    /// 榜单日期, 格式为 `20201201`
+    pub date: String,
+    pub page: u32,
+    pub next: Option<u32>,
+    pub items: Vec<RankingItem>,
+}
+
+impl TryFrom<&serde_json::Value> for RankingPage {
+    type Error = FromError;
+    fn try_from(value: &serde_json::Value) -> Result<Self, Self::Error> {
+        let contents = match value.get("contents").and_then(|x| x.as_array()) {
+            Some(x) => x,
+            None => return Err(FromError("contents必须存在".to_string())),
+        };
+        let mut items = Vec::new();
+        for item in contents {
+            let artwork_id = match item.get("illust_id").and_then(|x| x.as_i64()) {
+                Some(x) => x,
+                None => continue,
+            };
+            let rank = match item.get("rank").and_then(|x| x.as_i64()) {
+                Some(x) => x as i32,
+                None => continue,
+            };
+            items.push(RankingItem { artwork_id, rank });
+        }
+        let date = match value.get("date").and_then(|x| x.as_str()) {
+            Some(x) => x.to_string(),
+            None => return Err(FromError("date必须存在".to_string())),
+        };
+        Ok(RankingPage {
+            mode: value
+                .get("mode")
+                .and_then(|x| x.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            content: value
+                .get("content")
+                .and_then(|x| x.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            date,
+            page: value.get("page").and_then(|x| x.as_u64()).unwrap_or(1) as u32,
+            // 最后一页时 next 为 false
+            next: value.get("next").and_then(|x| x.as_u64()).map(|x| x as u32),
+            items,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ranking_page() {
+        let value = serde_json::json!({
+            "contents": [
+                {"illust_id": 84000001, "rank": 1, "title": "a"},
+                {"illust_id": 84000002, "rank": 2, "title": "b"},
+                {"rank": 3},
+            ],
+            "mode": "daily",
+            "content": "illust",
+            "page": 1,
+            "prev": false,
+            "next": 2,
+            "date": "20201201",
+            "rank_total": 500,
+        });
+        let page = RankingPage::try_from(&value).unwrap();
+        assert_eq!(page.mode, "daily");
+        assert_eq!(page.content, "illust");
+        assert_eq!(page.date, "20201201");
+        assert_eq!(page.page, 1);
+        assert_eq!(page.next, Some(2));
+        let items: Vec<(i64, i32)> = page.items.iter().map(|x| (x.artwork_id, x.rank)).collect();
+        assert_eq!(items, vec![(84000001, 1), (84000002, 2)]);
+    }
+
+    #[test]
+    fn last_page_and_missing_fields() {
+        let value = serde_json::json!({
+            "contents": [],
+            "mode": "weekly",
+            "page": 10,
+            "next": false,
+            "date": "20201201",
+        });
+        let page = RankingPage::try_from(&value).unwrap();
+        assert_eq!(page.page, 10);
+        assert_eq!(page.next, None);
+        assert_eq!(page.content, "");
+        assert!(page.items.is_empty());
+
+        assert!(RankingPage::try_from(&serde_json::json!({"date": "20201201"})).is_err());
+        assert!(RankingPage::try_from(&serde_json::json!({"contents": []})).is_err());
+    }
+}
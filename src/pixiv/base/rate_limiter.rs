@@ -0,0 +1,257 @@
+use log::warn;
+use serde::Deserialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 按接口类型分别限速
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endpoint {
+    Search,
+    Artwork,
+    Profile,
+    Image,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Budget {
+    /// 每秒允许的请求数
+    pub per_second: f64,
+    /// 令牌桶容量, 允许的瞬时突发请求数
+    pub burst: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub search: Budget,
+    pub artwork: Budget,
+    pub profile: Budget,
+    pub image: Budget,
+    /// 收到 429 后速率减半, 之后每隔这么多秒无 429 恢复一倍
+    pub recovery_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> RateLimitConfig {
+        RateLimitConfig {
+            search: Budget {
+                per_second: 1.0,
+                burst: 2,
+            },
+            artwork: Budget {
+                per_second: 2.0,
+                burst: 4,
+            },
+            profile: Budget {
+                per_second: 1.0,
+                burst: 2,
+            },
+            image: Budget {
+                per_second: 4.0,
+                burst: 8,
+            },
+            recovery_secs: 60,
+        }
+    }
+}
+
+const MIN_PENALTY: f64 = 1.0 / 16.0;
+
+struct Bucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    penalty: f64,
+    last_refill: Instant,
+    last_change: Instant,
+}
+
+impl Bucket {
+    fn new(budget: &Budget) -> Bucket {
+        let now = Instant::now();
+        let burst = (budget.burst.max(1)) as f64;
+        Bucket {
+            rate: budget.per_second.max(0.001),
+            burst,
+            tokens: burst,
+            penalty: 1.0,
+            last_refill: now,
+            last_change: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant, recovery: Duration) {
+        if self.penalty < 1.0 && now.duration_since(self.last_change) >= recovery {
+            self.penalty = (self.penalty * 2.0).min(1.0);
+            self.last_change = now;
+        }
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate * self.penalty).min(self.burst);
+        self.last_refill = now;
+    }
+
+    /// 取到令牌返回 None, 否则返回需要等待的时间
+    fn try_take(&mut self, now: Instant, recovery: Duration) -> Option<Duration> {
+        self.refill(now, recovery);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return None;
+        }
+        Some(Duration::from_secs_f64(
+            (1.0 - self.tokens) / (self.rate * self.penalty),
+        ))
+    }
+}
+
+/// 所有 PixivClient 共用的令牌桶限速器
+pub struct RateLimiter {
+    search: Mutex<Bucket>,
+    artwork: Mutex<Bucket>,
+    profile: Mutex<Bucket>,
+    image: Mutex<Bucket>,
+    recovery: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> RateLimiter {
+        RateLimiter {
+            search: Mutex::new(Bucket::new(&config.search)),
+            artwork: Mutex::new(Bucket::new(&config.artwork)),
+            profile: Mutex::new(Bucket::new(&config.profile)),
+            image: Mutex::new(Bucket::new(&config.image)),
+            recovery: Duration::from_secs(config.recovery_secs),
+        }
+    }
+
+    fn bucket(&self, endpoint: Endpoint) -> &Mutex<Bucket> {
+        match endpoint {
+            Endpoint::Search => &self.search,
+            Endpoint::Artwork => &self.artwork,
+            Endpoint::Profile => &self.profile,
+            Endpoint::Image => &self.image,
+        }
+    }
+
+    /// 等待直到拿到一个令牌
+    pub async fn acquire(&self, endpoint: Endpoint) {
+        loop {
+            let wait = self
+                .bucket(endpoint)
+                .lock()
+                .unwrap()
+                .try_take(Instant::now(), self.recovery);
+            match wait {
+                None => return,
+                Some(x) => async_std::task::sleep(x).await,
+            }
+        }
+    }
+
+    /// 收到 429 后把该接口的速率减半
+    pub fn throttled(&self, endpoint: Endpoint) {
+        let mut bucket = self.bucket(endpoint).lock().unwrap();
+        let now = Instant::now();
+        bucket.refill(now, self.recovery);
+        bucket.penalty = (bucket.penalty / 2.0).max(MIN_PENALTY);
+        bucket.tokens = bucket.tokens.min(0.0);
+        bucket.last_change = now;
+        warn!(
+            "{:?} 接口被限流, 速率降为 {:.3} 次/秒",
+            endpoint,
+            bucket.rate * bucket.penalty
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(per_second: f64, burst: u32) -> Budget {
+        Budget { per_second, burst }
+    }
+
+    #[test]
+    fn bucket_refills_up_to_burst() {
+        let recovery = Duration::from_secs(60);
+        let mut bucket = Bucket::new(&budget(2.0, 2));
+        let start = bucket.last_refill;
+        assert_eq!(bucket.try_take(start, recovery), None);
+        assert_eq!(bucket.try_take(start, recovery), None);
+        let wait = bucket.try_take(start, recovery).unwrap();
+        assert!((wait.as_secs_f64() - 0.5).abs() < 1e-6);
+        assert_eq!(bucket.try_take(start + wait, recovery), None);
+        // 空闲很久也只能攒到 burst 个令牌
+        let later = start + Duration::from_secs(100);
+        assert_eq!(bucket.try_take(later, recovery), None);
+        assert_eq!(bucket.try_take(later, recovery), None);
+        assert!(bucket.try_take(later, recovery).is_some());
+    }
+
+    #[test]
+    fn endpoints_have_separate_budgets() {
+        let mut config = RateLimitConfig::default();
+        config.search = budget(1.0, 1);
+        config.image = budget(1.0, 3);
+        let limiter = RateLimiter::new(&config);
+        let now = Instant::now();
+        let take = |endpoint| {
+            limiter
+                .bucket(endpoint)
+                .lock()
+                .unwrap()
+                .try_take(now, limiter.recovery)
+        };
+        assert_eq!(take(Endpoint::Search), None);
+        assert!(take(Endpoint::Search).is_some());
+        for _ in 0..3 {
+            assert_eq!(take(Endpoint::Image), None);
+        }
+        assert!(take(Endpoint::Image).is_some());
+        assert_eq!(take(Endpoint::Artwork), None);
+    }
+
+    #[test]
+    fn throttled_halves_rate_and_recovers() {
+        let mut config = RateLimitConfig::default();
+        config.artwork = budget(4.0, 1);
+        config.recovery_secs = 10;
+        let limiter = RateLimiter::new(&config);
+        limiter.throttled(Endpoint::Artwork);
+        let mut bucket = limiter.artwork.lock().unwrap();
+        assert_eq!(bucket.penalty, 0.5);
+        let now = bucket.last_change;
+        // 令牌被清空, 按减半后的 2 次/秒 等待
+        let wait = bucket.try_take(now, limiter.recovery).unwrap();
+        assert!((wait.as_secs_f64() - 0.5).abs() < 1e-6);
+        drop(bucket);
+
+        for _ in 0..10 {
+            limiter.throttled(Endpoint::Artwork);
+        }
+        let mut bucket = limiter.artwork.lock().unwrap();
+        assert_eq!(bucket.penalty, MIN_PENALTY);
+        // 每个恢复周期没有 429 速率翻倍, 最多回到原来的速率
+        let mut now = bucket.last_change;
+        for expected in &[0.125, 0.25, 0.5, 1.0, 1.0] {
+            now += limiter.recovery;
+            bucket.refill(now, limiter.recovery);
+            assert_eq!(bucket.penalty, *expected);
+        }
+        // 其他接口不受影响
+        assert_eq!(limiter.search.lock().unwrap().penalty, 1.0);
+    }
+
+    #[test]
+    fn acquire_waits_for_a_token() {
+        let mut config = RateLimitConfig::default();
+        config.profile = budget(50.0, 1);
+        let limiter = RateLimiter::new(&config);
+        let start = Instant::now();
+        async_std::task::block_on(async {
+            limiter.acquire(Endpoint::Profile).await;
+            limiter.acquire(Endpoint::Profile).await;
+        });
+        assert!(start.elapsed() >= Duration::from_millis(15));
+    }
+}
@@ -0,0 +1,109 @@
+use rand::Rng;
+use serde::Deserialize;
+use std::time::Duration;
+
+/// 请求失败后的重试策略: 指数退避 + 随机抖动, 服务端给出 `Retry-After` 时优先使用
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// 包括第一次请求在内的最大尝试次数
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay_ms: 1000,
+            max_delay_ms: 30000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// 第 `attempt` 次 (从 1 开始) 失败后的等待时间, 在 [delay/2, delay] 之间随机
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay_ms
+            .saturating_mul(1u64 << (attempt.saturating_sub(1)).min(20));
+        let delay = exp.min(self.max_delay_ms).max(1);
+        let jitter = rand::thread_rng().gen_range(0, delay / 2 + 1);
+        Duration::from_millis(delay - jitter)
+    }
+
+    /// 解析 `Retry-After` (秒数或 HTTP 日期), 结果不超过 `max_delay_ms`
+    pub fn retry_after(&self, value: &str) -> Option<Duration> {
+        let value = value.trim();
+        let secs = match value.parse::<u64>() {
+            Ok(x) => x,
+            Err(_) => {
+                let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+                (date.timestamp() - chrono::Utc::now().timestamp()).max(0) as u64
+            }
+        };
+        Some(Duration::from_millis(
+            secs.saturating_mul(1000).min(self.max_delay_ms),
+        ))
+    }
+
+    pub fn is_retryable_status(status: u16) -> bool {
+        status == 429 || (500..600).contains(&status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay_ms: 100,
+            max_delay_ms: 1000,
+        };
+        for _ in 0..50 {
+            let first = policy.backoff(1).as_millis();
+            assert!((50..=100).contains(&first), "{}", first);
+            let third = policy.backoff(3).as_millis();
+            assert!((200..=400).contains(&third), "{}", third);
+            let capped = policy.backoff(30).as_millis();
+            assert!((500..=1000).contains(&capped), "{}", capped);
+        }
+    }
+
+    #[test]
+    fn retry_after_seconds_and_date() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.retry_after(" 5 "), Some(Duration::from_secs(5)));
+        assert_eq!(
+            policy.retry_after("3600"),
+            Some(Duration::from_millis(30000))
+        );
+        assert_eq!(
+            policy.retry_after("Thu, 01 Jan 1970 00:00:00 GMT"),
+            Some(Duration::from_secs(0))
+        );
+        let date = (chrono::Utc::now() + chrono::Duration::seconds(20)).to_rfc2822();
+        let delay = policy.retry_after(&date).unwrap().as_secs();
+        assert!((18..=20).contains(&delay), "{}", delay);
+        assert_eq!(policy.retry_after("soon"), None);
+    }
+
+    #[test]
+    fn retryable_status() {
+        assert!(RetryPolicy::is_retryable_status(429));
+        assert!(RetryPolicy::is_retryable_status(503));
+        assert!(!RetryPolicy::is_retryable_status(404));
+        assert!(!RetryPolicy::is_retryable_status(403));
+    }
+}
@@ -0,0 +1,216 @@
+use super::artwork::FromError;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// 系列中的一话
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PixivSeriesItem {
+    #[serde(rename(serialize = "id", deserialize = "id"))]
+    pub artwork_id: i64,
+    #[serde(rename(serialize = "order", deserialize = "order"))]
+    pub order: i32,
+}
+
+/// 漫画/插画系列, 单独保存在 Series 集合中, `items` 按 `order` 排序
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PixivSeries {
+    #[serde(
+        rename(serialize = "_id", deserialize = "_id"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub _id: Option<ObjectId>,
+    #[serde(rename(serialize = "id", deserialize = "id"))]
+    pub series_id: i64,
+    #[serde(
+        rename(serialize = "title", deserialize = "title"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub title: Option<String>,
+    #[serde(
+        rename(serialize = "caption", deserialize = "caption"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub caption: Option<String>,
+    #[serde(
+        rename(serialize = "user_id", deserialize = "user_id"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub user_id: Option<i64>,
+    /// 系列的总话数
+    #[serde(
+        rename(serialize = "total", deserialize = "total"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub total: Option<i32>,
+    #[serde(
+        rename(serialize = "cover_url", deserialize = "cover_url"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cover_url: Option<String>,
+    #[serde(
+        rename(serialize = "create_date", deserialize = "create_date"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub create_date: Option<String>,
+    #[serde(
+        rename(serialize = "update_date", deserialize = "update_date"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub update_date: Option<String>,
+    #[serde(
+        rename(serialize = "items", deserialize = "items"),
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub items: Vec<PixivSeriesItem>,
+    #[serde(
+        rename(serialize = "last_update_time", deserialize = "last_update_time"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_update_time: Option<i64>,
+}
+
+/// id 有时是字符串有时是数字
+fn id_value(value: Option<&serde_json::Value>) -> Option<i64> {
+    match value? {
+        serde_json::Value::String(x) => x.parse::<i64>().ok(),
+        x => x.as_i64(),
+    }
+}
+
+fn string_value(value: &serde_json::Value, field: &str) -> Option<String> {
+    value
+        .get(field)
+        .and_then(|x| x.as_str())
+        .map(|x| x.to_string())
+}
+
+impl TryFrom<(i64, &serde_json::Value)> for PixivSeries {
+    type Error = FromError;
+
+    /// `ajax/series/{id}?p={page}` 的 `body`, `items` 只包含这一页
+    fn try_from((series_id, body): (i64, &serde_json::Value)) -> Result<Self, Self::Error> {
+        let empty = Vec::new();
+        let meta = body
+            .get("illustSeries")
+            .and_then(|x| x.as_array())
+            .unwrap_or(&empty)
+            .iter()
+            .find(|x| id_value(x.get("id")) == Some(series_id));
+        let meta = match meta {
+            Some(x) => x,
+            None => return Err(FromError(format!("illustSeries中没有系列 {}", series_id))),
+        };
+        let page = match body.get("page") {
+            Some(x) => x,
+            None => return Err(FromError("page必须存在".to_string())),
+        };
+        let mut items = Vec::new();
+        for item in page
+            .get("series")
+            .and_then(|x| x.as_array())
+            .unwrap_or(&empty)
+        {
+            let artwork_id = match id_value(item.get("workId")) {
+                Some(x) => x,
+                None => continue,
+            };
+            let order = item.get("order").and_then(|x| x.as_i64()).unwrap_or(0) as i32;
+            items.push(PixivSeriesItem { artwork_id, order });
+        }
+        Ok(PixivSeries {
+            _id: None,
+            series_id,
+            title: string_value(meta, "title"),
+            caption: string_value(meta, "caption"),
+            user_id: id_value(meta.get("userId")),
+            total: meta
+                .get("total")
+                .or_else(|| page.get("total"))
+                .and_then(|x| x.as_i64())
+                .map(|x| x as i32),
+            cover_url: string_value(meta, "url"),
+            create_date: string_value(meta, "createDate"),
+            update_date: string_value(meta, "updateDate"),
+            items,
+            last_update_time: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body() -> serde_json::Value {
+        serde_json::json!({
+            "illustSeries": [
+                {"id": "120002", "title": "別のシリーズ", "userId": "12", "total": 1},
+                {
+                    "id": "120001",
+                    "title": "シリーズ",
+                    "caption": "説明",
+                    "userId": "11",
+                    "total": 5,
+                    "url": "https://i.pximg.net/c/240x480_80/img-master/img/2020/12/01/00/00/00/84000001_p0_master1200.jpg",
+                    "createDate": "2020-12-01T00:00:00+09:00",
+                    "updateDate": "2020-12-08T00:00:00+09:00",
+                },
+            ],
+            "page": {
+                "series": [
+                    {"workId": "84000003", "order": 3},
+                    {"workId": "84000001", "order": 1},
+                    {"order": 2},
+                ],
+                "total": 5,
+            },
+        })
+    }
+
+    #[test]
+    fn series_from_ajax_body() {
+        let series = PixivSeries::try_from((120001, &body())).unwrap();
+        assert_eq!(series.series_id, 120001);
+        assert_eq!(series.title.as_deref(), Some("シリーズ"));
+        assert_eq!(series.caption.as_deref(), Some("説明"));
+        assert_eq!(series.user_id, Some(11));
+        assert_eq!(series.total, Some(5));
+        assert!(series
+            .cover_url
+            .unwrap()
+            .ends_with("84000001_p0_master1200.jpg"));
+        assert_eq!(
+            series.update_date.as_deref(),
+            Some("2020-12-08T00:00:00+09:00")
+        );
+        // 缺少 workId 的条目跳过, 排序由调用方负责
+        let items: Vec<(i64, i32)> = series
+            .items
+            .iter()
+            .map(|x| (x.artwork_id, x.order))
+            .collect();
+        assert_eq!(items, vec![(84000003, 3), (84000001, 1)]);
+    }
+
+    #[test]
+    fn series_total_falls_back_to_page() {
+        let mut body = body();
+        body["illustSeries"][1]
+            .as_object_mut()
+            .unwrap()
+            .remove("total");
+        let series = PixivSeries::try_from((120001, &body)).unwrap();
+        assert_eq!(series.total, Some(5));
+    }
+
+    #[test]
+    fn series_requires_meta_and_page() {
+        assert!(PixivSeries::try_from((120003, &body())).is_err());
+        let mut body = body();
+        body.as_object_mut().unwrap().remove("page");
+        assert!(PixivSeries::try_from((120001, &body)).is_err());
+        assert!(PixivSeries::try_from((120001, &serde_json::json!({}))).is_err());
+    }
+}
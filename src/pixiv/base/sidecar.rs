@@ -0,0 +1,256 @@
+use super::artwork::{Artwork, ArtworkType};
+use serde::Deserialize;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SidecarFormat {
+    /// `Artwork` 原样序列化, `{图片}.artwork.json`
+    Json,
+    /// gallery-dl 的 `--write-metadata` 格式, `{图片}.json`
+    GalleryDl,
+    /// Hydrus/booru 风格的标签列表, 每行一个, `{图片}.txt`
+    Tags,
+}
+
+impl SidecarFormat {
+    fn suffix(&self) -> &'static str {
+        match self {
+            SidecarFormat::Json => "artwork.json",
+            SidecarFormat::GalleryDl => "json",
+            SidecarFormat::Tags => "txt",
+        }
+    }
+}
+
+/// 附属文件路径: 在图片完整文件名后追加后缀
+pub fn sidecar_path(image: &Path, format: SidecarFormat) -> PathBuf {
+    let mut path = image.as_os_str().to_owned();
+    path.push(".");
+    path.push(format.suffix());
+    PathBuf::from(path)
+}
+
+/// pixiv 的 `createDate` 转成 gallery-dl 使用的 `YYYY-MM-DD HH:MM:SS` (UTC)
+fn gallery_dl_date(create_date: &str) -> Option<String> {
+    let date = chrono::DateTime::parse_from_rfc3339(create_date).ok()?;
+    Some(
+        date.with_timezone(&chrono::Utc)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string(),
+    )
+}
+
+fn gallery_dl_json(artwork: &Artwork, page: usize, url: &str) -> serde_json::Value {
+    let name = url.rsplit('/').next().unwrap_or(url);
+    let name = name.split('?').next().unwrap_or(name);
+    let (filename, extension) = match name.rfind('.') {
+        Some(pos) => (&name[..pos], &name[pos + 1..]),
+        None => (name, ""),
+    };
+    let kind = match artwork.artwork_type {
+        Some(ArtworkType::Illust) => "illust",
+        Some(ArtworkType::Manga) => "manga",
+        Some(ArtworkType::Ugoira) => "ugoira",
+        _ => "illust",
+    };
+    let tags = artwork
+        .tags
+        .iter()
+        .flatten()
+        .map(|x| x.name.clone())
+        .collect::<Vec<_>>();
+    let user = artwork.user.as_ref();
+    json!({
+        "category" : "pixiv",
+        "subcategory" : "artworks",
+        "id" : artwork.artwork_id,
+        "title" : artwork.title,
+        "caption" : artwork.caption,
+        "type" : kind,
+        "user" : {
+            "id" : user.and_then(|x| x.user_id),
+            "name" : user.and_then(|x| x.name.clone()),
+            "account" : user.and_then(|x| x.account.clone()),
+        },
+        "tags" : tags,
+        "date" : artwork.create_date.as_deref().and_then(gallery_dl_date),
+        "width" : artwork.width,
+        "height" : artwork.height,
+        "page_count" : artwork.page_count.unwrap_or(1),
+        "sanity_level" : artwork.sanity_level,
+        "total_bookmarks" : artwork.total_bookmarks,
+        "total_view" : artwork.total_view,
+        "num" : page,
+        "url" : url,
+        "filename" : filename,
+        "extension" : extension,
+    })
+}
+
+/// 标签名和翻译各占一行, 作者/标题/来源使用 `creator:` `title:` `source:` 命名空间
+fn tag_lines(artwork: &Artwork) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut push = |line: String| {
+        let line = line.trim().to_string();
+        if !line.is_empty() && !lines.contains(&line) {
+            lines.push(line);
+        }
+    };
+    for tag in artwork.tags.iter().flatten() {
+        push(tag.name.clone());
+        if let Some(ref trans) = tag.trans {
+            push(trans.clone());
+        }
+    }
+    if let Some(name) = artwork.user.as_ref().and_then(|x| x.name.as_ref()) {
+        push(format!("creator:{}", name));
+    }
+    if let Some(ref title) = artwork.title {
+        push(format!("title:{}", title));
+    }
+    push(format!(
+        "source:https://www.pixiv.net/artworks/{}",
+        artwork.artwork_id
+    ));
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
+
+/// 为一页图片写入附属文件, 返回写入的路径
+///
+/// `page` 从 0 开始, `url` 为该页原图地址
+pub fn write_sidecar(
+    artwork: &Artwork,
+    page: usize,
+    url: &str,
+    image: &Path,
+    format: SidecarFormat,
+) -> std::io::Result<PathBuf> {
+    let content = match format {
+        SidecarFormat::Json => serde_json::to_string_pretty(artwork)?,
+        SidecarFormat::GalleryDl => {
+            serde_json::to_string_pretty(&gallery_dl_json(artwork, page, url))?
+        }
+        SidecarFormat::Tags => tag_lines(artwork),
+    };
+    let path = sidecar_path(image, format);
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".part");
+    std::fs::write(&temp, content)?;
+    std::fs::rename(&temp, &path)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    const ORIGINAL: &str =
+        "https://i.pximg.net/img-original/img/2020/12/01/09/00/00/84000001_p1.png";
+
+    fn artwork() -> Artwork {
+        Artwork::try_from(&serde_json::json!({
+            "id": "84000001",
+            "title": "タイトル",
+            "illustType": 1,
+            "illustComment": "caption",
+            "createDate": "2020-12-01T09:00:00+09:00",
+            "pageCount": 2,
+            "width": 1200,
+            "height": 1700,
+            "sl": 2,
+            "bookmarkCount": 10,
+            "viewCount": 100,
+            "userId": "11",
+            "userName": "pixiv事務局",
+            "userAccount": "pixiv",
+            "urls": {"original": ORIGINAL},
+            "tags": {"tags": [
+                {"tag": "オリジナル", "translation": {"en": "original"}},
+                {"tag": "漫画"},
+                {"tag": "original"},
+            ]},
+        }))
+        .unwrap()
+    }
+
+    fn temp_image(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("pixiv-sidecar-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("84000001_p1.png")
+    }
+
+    #[test]
+    fn sidecar_path_appends_suffix() {
+        let image = Path::new("/data/11/84000001_p1.png");
+        assert_eq!(
+            sidecar_path(image, SidecarFormat::Json),
+            Path::new("/data/11/84000001_p1.png.artwork.json")
+        );
+        assert_eq!(
+            sidecar_path(image, SidecarFormat::GalleryDl),
+            Path::new("/data/11/84000001_p1.png.json")
+        );
+        assert_eq!(
+            sidecar_path(image, SidecarFormat::Tags),
+            Path::new("/data/11/84000001_p1.png.txt")
+        );
+    }
+
+    #[test]
+    fn json_sidecar_round_trips() {
+        let artwork = artwork();
+        let image = temp_image("json");
+        let path = write_sidecar(&artwork, 1, ORIGINAL, &image, SidecarFormat::Json).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let parsed: Artwork = serde_json::from_str(&content).unwrap();
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::to_value(&artwork).unwrap()
+        );
+        assert_eq!(path, sidecar_path(&image, SidecarFormat::Json));
+        let _ = std::fs::remove_dir_all(image.parent().unwrap());
+    }
+
+    #[test]
+    fn gallery_dl_sidecar_keys() {
+        let image = temp_image("gallery-dl");
+        let path =
+            write_sidecar(&artwork(), 1, ORIGINAL, &image, SidecarFormat::GalleryDl).unwrap();
+        let value: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(value["category"], "pixiv");
+        assert_eq!(value["id"], 84000001);
+        assert_eq!(value["type"], "manga");
+        assert_eq!(value["user"]["id"], 11);
+        assert_eq!(value["user"]["account"], "pixiv");
+        assert_eq!(
+            value["tags"],
+            serde_json::json!(["オリジナル", "漫画", "original"])
+        );
+        // 转换为 UTC
+        assert_eq!(value["date"], "2020-12-01 00:00:00");
+        assert_eq!(value["page_count"], 2);
+        assert_eq!(value["num"], 1);
+        assert_eq!(value["filename"], "84000001_p1");
+        assert_eq!(value["extension"], "png");
+        let _ = std::fs::remove_dir_all(image.parent().unwrap());
+    }
+
+    #[test]
+    fn tags_sidecar_lists_names_and_translations() {
+        let image = temp_image("tags");
+        let path = write_sidecar(&artwork(), 1, ORIGINAL, &image, SidecarFormat::Tags).unwrap();
+        // 翻译与已有标签重复时只保留一行
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "オリジナル\noriginal\n漫画\ncreator:pixiv事務局\ntitle:タイトル\nsource:https://www.pixiv.net/artworks/84000001\n"
+        );
+        let _ = std::fs::remove_dir_all(image.parent().unwrap());
+    }
+}
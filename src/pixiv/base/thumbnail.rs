@@ -0,0 +1,205 @@
+use super::artwork::PixivThumbnail;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailFormat {
+    Jpeg,
+    /// 纯 Rust 的 WebP 编码器只支持无损压缩
+    Webp,
+}
+
+impl ThumbnailFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "jpg",
+            ThumbnailFormat::Webp => "webp",
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ThumbnailError {
+    #[error("图片解码/编码错误 : {0:?}")]
+    Image(#[from] image::ImageError),
+    #[error("IO错误 : {0:?}")]
+    Io(#[from] std::io::Error),
+}
+
+/// 缩略图路径: `{dir}/{作品id}_p{页码}_{长边}.{ext}`
+///
+/// 未指定 `dir` 时与原图放在同一目录, 文件名为 `{原文件名}_{长边}.{ext}`;
+/// 集中存放时不同作品的原图可能同名, 所以用作品id和页码命名
+pub fn thumbnail_path(
+    source: &Path,
+    dir: Option<&Path>,
+    artwork_id: i64,
+    page: i32,
+    long_edge: u32,
+    format: ThumbnailFormat,
+) -> PathBuf {
+    match dir {
+        Some(x) => x.join(format!(
+            "{}_p{}_{}.{}",
+            artwork_id,
+            page,
+            long_edge,
+            format.extension()
+        )),
+        None => {
+            let stem = source
+                .file_stem()
+                .map(|x| x.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let dir = source.parent().map(|x| x.to_path_buf()).unwrap_or_default();
+            dir.join(format!("{}_{}.{}", stem, long_edge, format.extension()))
+        }
+    }
+}
+
+fn write_thumbnail(
+    image: &image::DynamicImage,
+    path: &Path,
+    format: ThumbnailFormat,
+) -> Result<(), ThumbnailError> {
+    use image::ImageEncoder;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".part");
+    {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&temp)?);
+        match format {
+            ThumbnailFormat::Jpeg => {
+                let rgb = image.to_rgb8();
+                image::codecs::jpeg::JpegEncoder::new_with_quality(&mut file, 85).write_image(
+                    rgb.as_raw(),
+                    rgb.width(),
+                    rgb.height(),
+                    image::ColorType::Rgb8,
+                )?;
+            }
+            ThumbnailFormat::Webp => {
+                let rgba = image.to_rgba8();
+                image::codecs::webp::WebPEncoder::new_lossless(&mut file).write_image(
+                    rgba.as_raw(),
+                    rgba.width(),
+                    rgba.height(),
+                    image::ColorType::Rgba8,
+                )?;
+            }
+        }
+    }
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// 为一张原图生成多个尺寸的缩略图, 已存在的缩略图直接读取尺寸, 不重复生成
+///
+/// 原图比目标尺寸小时不放大, 按原尺寸输出
+pub fn generate_thumbnails(
+    source: &Path,
+    dir: Option<&Path>,
+    artwork_id: i64,
+    page: i32,
+    long_edges: &[u32],
+    format: ThumbnailFormat,
+) -> Result<Vec<PixivThumbnail>, ThumbnailError> {
+    let mut image: Option<image::DynamicImage> = None;
+    let mut thumbnails = Vec::new();
+    for &long_edge in long_edges {
+        let path = thumbnail_path(source, dir, artwork_id, page, long_edge, format);
+        let (width, height) = match image::image_dimensions(&path) {
+            Ok(x) => x,
+            Err(_) => {
+                if image.is_none() {
+                    image = Some(image::open(source)?);
+                }
+                let original = image.as_ref().unwrap();
+                let resized = if original.width().max(original.height()) > long_edge {
+                    original.resize(long_edge, long_edge, image::imageops::FilterType::Lanczos3)
+                } else {
+                    original.clone()
+                };
+                write_thumbnail(&resized, &path, format)?;
+                (resized.width(), resized.height())
+            }
+        };
+        thumbnails.push(PixivThumbnail {
+            long_edge: long_edge as i32,
+            path: path.to_string_lossy().to_string(),
+            width: width as i32,
+            height: height as i32,
+        });
+    }
+    Ok(thumbnails)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_dir_names_include_artwork_and_page() {
+        let dir = Path::new("/thumbs");
+        let a = thumbnail_path(
+            Path::new("/img/1/p0.jpg"),
+            Some(dir),
+            1,
+            0,
+            300,
+            ThumbnailFormat::Jpeg,
+        );
+        let b = thumbnail_path(
+            Path::new("/img/2/p0.jpg"),
+            Some(dir),
+            2,
+            0,
+            300,
+            ThumbnailFormat::Jpeg,
+        );
+        assert_eq!(a, Path::new("/thumbs/1_p0_300.jpg"));
+        assert_eq!(b, Path::new("/thumbs/2_p0_300.jpg"));
+        let c = thumbnail_path(
+            Path::new("/img/1/p0.png"),
+            None,
+            1,
+            0,
+            300,
+            ThumbnailFormat::Webp,
+        );
+        assert_eq!(c, Path::new("/img/1/p0_300.webp"));
+    }
+
+    #[test]
+    fn generates_and_reuses_thumbnails() {
+        let dir = std::env::temp_dir().join(format!("pixiv-thumbnail-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("source.png");
+        image::RgbImage::from_pixel(400, 200, image::Rgb([10, 20, 30]))
+            .save(&source)
+            .unwrap();
+        let thumbnails = generate_thumbnails(
+            &source,
+            Some(&dir),
+            1,
+            0,
+            &[100, 800],
+            ThumbnailFormat::Jpeg,
+        )
+        .unwrap();
+        assert_eq!(thumbnails.len(), 2);
+        assert_eq!((thumbnails[0].width, thumbnails[0].height), (100, 50));
+        // 原图比目标尺寸小时不放大
+        assert_eq!((thumbnails[1].width, thumbnails[1].height), (400, 200));
+        std::fs::remove_file(&source).unwrap();
+        // 缩略图已存在时不再读取原图
+        let again =
+            generate_thumbnails(&source, Some(&dir), 1, 0, &[100], ThumbnailFormat::Jpeg).unwrap();
+        assert_eq!((again[0].width, again[0].height), (100, 50));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+use super::PixivError;
+use futures::{io::Cursor, AsyncRead};
+use std::{collections::HashMap, future::Future, path::Path, pin::Pin};
+
+pub type ResponseBody = Box<dyn AsyncRead + Unpin + Send>;
+pub type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<TransportResponse, PixivError>> + Send + 'a>>;
+
+pub struct TransportResponse {
+    pub status: u16,
+    pub headers: http::HeaderMap,
+    pub body: ResponseBody,
+}
+
+/// PixivClient 的底层 HTTP 传输, 便于替换成离线实现
+pub trait Transport: Send + Sync {
+    fn send(&self, request: http::Request<()>) -> TransportFuture<'_>;
+}
+
+pub struct IsahcTransport {
+    client: isahc::HttpClient,
+}
+
+impl IsahcTransport {
+    pub fn new(client: isahc::HttpClient) -> IsahcTransport {
+        IsahcTransport { client }
+    }
+}
+
+impl Transport for IsahcTransport {
+    fn send(&self, request: http::Request<()>) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let response = self.client.send_async(request).await?;
+            let (parts, body) = response.into_parts();
+            Ok(TransportResponse {
+                status: parts.status.as_u16(),
+                headers: parts.headers,
+                body: Box::new(body) as ResponseBody,
+            })
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Fixture {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// 按 URL 返回预先录制好的响应, 不访问网络
+///
+/// 先按完整 URL 匹配, 找不到时再忽略查询参数按路径匹配, 都找不到返回 404
+pub struct FixtureTransport {
+    fixtures: HashMap<String, Fixture>,
+}
+
+fn strip_query(url: &str) -> &str {
+    match url.find('?') {
+        Some(pos) => &url[..pos],
+        None => url,
+    }
+}
+
+impl FixtureTransport {
+    pub fn new() -> FixtureTransport {
+        FixtureTransport {
+            fixtures: HashMap::new(),
+        }
+    }
+    pub fn insert(&mut self, url: &str, fixture: Fixture) {
+        self.fixtures.insert(url.to_string(), fixture);
+    }
+    pub fn insert_body(&mut self, url: &str, status: u16, body: impl Into<Vec<u8>>) {
+        self.insert(
+            url,
+            Fixture {
+                status,
+                headers: Vec::new(),
+                body: body.into(),
+            },
+        );
+    }
+    pub fn insert_file(&mut self, url: &str, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let body = std::fs::read(path)?;
+        self.insert_body(url, 200, body);
+        Ok(())
+    }
+    fn find(&self, url: &str) -> Option<&Fixture> {
+        if let Some(x) = self.fixtures.get(url) {
+            return Some(x);
+        }
+        let path = strip_query(url);
+        self.fixtures
+            .iter()
+            .find(|(k, _)| strip_query(k) == path)
+            .map(|(_, v)| v)
+    }
+}
+
+impl Transport for FixtureTransport {
+    fn send(&self, request: http::Request<()>) -> TransportFuture<'_> {
+        let url = request.uri().to_string();
+        let fixture = self.find(&url).cloned().unwrap_or(Fixture {
+            status: 404,
+            headers: Vec::new(),
+            body: Vec::new(),
+        });
+        Box::pin(async move {
+            let mut headers = http::HeaderMap::new();
+            for (k, v) in &fixture.headers {
+                let name = match http::header::HeaderName::from_bytes(k.as_bytes()) {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                };
+                if let Ok(value) = http::HeaderValue::from_str(v) {
+                    headers.append(name, value);
+                }
+            }
+            Ok(TransportResponse {
+                status: fixture.status,
+                headers,
+                body: Box::new(Cursor::new(fixture.body)) as ResponseBody,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::AsyncReadExt;
+
+    fn send(transport: &FixtureTransport, url: &str) -> (u16, http::HeaderMap, Vec<u8>) {
+        async_std::task::block_on(async {
+            let request = http::Request::get(url).body(()).unwrap();
+            let mut response = transport.send(request).await.unwrap();
+            let mut body = Vec::new();
+            response.body.read_to_end(&mut body).await.unwrap();
+            (response.status, response.headers, body)
+        })
+    }
+
+    #[test]
+    fn fixture_matches_full_url_first() {
+        let mut transport = FixtureTransport::new();
+        transport.insert_body("https://www.pixiv.net/artworks/1?lang=zh", 200, "zh");
+        transport.insert_body("https://www.pixiv.net/artworks/1?lang=ja", 200, "ja");
+        let (status, _, body) = send(&transport, "https://www.pixiv.net/artworks/1?lang=ja");
+        assert_eq!(status, 200);
+        assert_eq!(body, b"ja");
+    }
+
+    #[test]
+    fn fixture_falls_back_to_path() {
+        let mut transport = FixtureTransport::new();
+        transport.insert_body("https://www.pixiv.net/ajax/illust/1/pages", 200, "pages");
+        let (status, _, body) = send(
+            &transport,
+            "https://www.pixiv.net/ajax/illust/1/pages?lang=zh",
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body, b"pages");
+    }
+
+    #[test]
+    fn fixture_missing_returns_404() {
+        let transport = FixtureTransport::new();
+        let (status, _, body) = send(&transport, "https://www.pixiv.net/artworks/2");
+        assert_eq!(status, 404);
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn fixture_keeps_repeated_headers() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(
+            "https://www.pixiv.net/",
+            Fixture {
+                status: 200,
+                headers: vec![
+                    ("Set-Cookie".into(), "a=1".into()),
+                    ("Set-Cookie".into(), "b=2".into()),
+                    ("bad header".into(), "x".into()),
+                ],
+                body: Vec::new(),
+            },
+        );
+        let (_, headers, _) = send(&transport, "https://www.pixiv.net/");
+        let cookies: Vec<&str> = headers
+            .get_all(http::header::SET_COOKIE)
+            .iter()
+            .map(|x| x.to_str().unwrap())
+            .collect();
+        assert_eq!(cookies, vec!["a=1", "b=2"]);
+        assert_eq!(headers.len(), 2);
+    }
+}
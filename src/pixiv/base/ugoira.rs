@@ -0,0 +1,364 @@
+use super::artwork::UgoiraFrame;
+use std::io::{Cursor, Read};
+
+/// 动图转换的输出格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+    Webp,
+}
+
+impl AnimationFormat {
+    /// 按输出文件的扩展名判断格式, `.png` 视为 APNG
+    pub fn from_path(path: &str) -> Option<AnimationFormat> {
+        let ext = std::path::Path::new(path)
+            .extension()?
+            .to_str()?
+            .to_ascii_lowercase();
+        match ext.as_str() {
+            "gif" => Some(AnimationFormat::Gif),
+            "png" | "apng" => Some(AnimationFormat::Apng),
+            "webp" => Some(AnimationFormat::Webp),
+            _ => None,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum UgoiraError {
+    #[error("压缩包错误 : {0:?}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("图片解码/编码错误 : {0:?}")]
+    Image(#[from] image::ImageError),
+    #[error("APNG编码错误 : {0:?}")]
+    Png(#[from] png::EncodingError),
+    #[error("IO错误 : {0:?}")]
+    Io(#[from] std::io::Error),
+    #[error("压缩包中没有帧 {0}")]
+    MissingFrame(String),
+    #[error("没有可用的帧")]
+    EmptyFrames,
+    #[error("帧尺寸不一致 : {0}")]
+    SizeMismatch(String),
+}
+
+type Result<T> = std::result::Result<T, UgoiraError>;
+
+struct DecodedFrame {
+    image: image::RgbaImage,
+    delay: u32,
+}
+
+/// 压缩包中的所有帧按文件名排序, 用于没有 ugoira_meta 的情况
+pub fn frames_from_zip(zip_data: &[u8], delay: i32) -> Result<Vec<UgoiraFrame>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(zip_data))?;
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        if file.is_file() {
+            files.push(file.name().to_string());
+        }
+    }
+    files.sort();
+    Ok(files
+        .into_iter()
+        .map(|file| UgoiraFrame { file, delay })
+        .collect())
+}
+
+fn decode_frames(zip_data: &[u8], frames: &[UgoiraFrame]) -> Result<Vec<DecodedFrame>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(zip_data))?;
+    let mut decoded: Vec<DecodedFrame> = Vec::new();
+    for frame in frames {
+        let mut data = Vec::new();
+        match archive.by_name(&frame.file) {
+            Ok(mut x) => x.read_to_end(&mut data)?,
+            Err(zip::result::ZipError::FileNotFound) => {
+                return Err(UgoiraError::MissingFrame(frame.file.clone()))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let image = image::load_from_memory(&data)?.to_rgba8();
+        if let Some(first) = decoded.first() {
+            if first.image.dimensions() != image.dimensions() {
+                return Err(UgoiraError::SizeMismatch(format!(
+                    "{} {:?} != {:?}",
+                    frame.file,
+                    image.dimensions(),
+                    first.image.dimensions()
+                )));
+            }
+        }
+        decoded.push(DecodedFrame {
+            image,
+            delay: frame.delay.max(1) as u32,
+        });
+    }
+    if decoded.is_empty() {
+        return Err(UgoiraError::EmptyFrames);
+    }
+    Ok(decoded)
+}
+
+fn encode_gif(frames: Vec<DecodedFrame>) -> Result<Vec<u8>> {
+    use image::codecs::gif::{GifEncoder, Repeat};
+    let mut out = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut out);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames.into_iter().map(|x| {
+            image::Frame::from_parts(x.image, 0, 0, image::Delay::from_numer_denom_ms(x.delay, 1))
+        }))?;
+    }
+    Ok(out)
+}
+
+fn encode_apng(frames: Vec<DecodedFrame>) -> Result<Vec<u8>> {
+    let (width, height) = frames[0].image.dimensions();
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(frames.len() as u32, 0)?;
+        let mut writer = encoder.write_header()?;
+        for frame in &frames {
+            writer.set_frame_delay(frame.delay.min(u16::MAX as u32) as u16, 1000)?;
+            writer.write_image_data(frame.image.as_raw())?;
+        }
+        writer.finish()?;
+    }
+    Ok(out)
+}
+
+fn push_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], payload: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+fn push_u24(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes()[..3]);
+}
+
+/// 从单帧 WebP 文件中取出 VP8L 数据块
+fn extract_vp8l(webp: &[u8]) -> Option<&[u8]> {
+    let mut pos = 12;
+    while pos + 8 <= webp.len() {
+        let size = u32::from_le_bytes([webp[pos + 4], webp[pos + 5], webp[pos + 6], webp[pos + 7]])
+            as usize;
+        let start = pos + 8;
+        if start + size > webp.len() {
+            return None;
+        }
+        if &webp[pos..pos + 4] == b"VP8L" {
+            return Some(&webp[start..start + size]);
+        }
+        pos = start + size + size % 2;
+    }
+    None
+}
+
+/// 每帧用无损 VP8L 编码, 再按 WebP 容器格式组装 VP8X/ANIM/ANMF 块
+fn encode_webp(frames: Vec<DecodedFrame>) -> Result<Vec<u8>> {
+    use image::codecs::webp::WebPEncoder;
+    let (width, height) = frames[0].image.dimensions();
+    let mut chunks = Vec::new();
+
+    let mut vp8x = vec![0x10 | 0x02, 0, 0, 0];
+    push_u24(&mut vp8x, width - 1);
+    push_u24(&mut vp8x, height - 1);
+    push_chunk(&mut chunks, b"VP8X", &vp8x);
+
+    // 背景色 + 循环次数 (0 表示无限循环)
+    push_chunk(&mut chunks, b"ANIM", &[0, 0, 0, 0, 0, 0]);
+
+    for frame in &frames {
+        let mut single = Vec::new();
+        WebPEncoder::new_lossless(&mut single).encode(
+            frame.image.as_raw(),
+            width,
+            height,
+            image::ColorType::Rgba8,
+        )?;
+        let vp8l = match extract_vp8l(&single) {
+            Some(x) => x,
+            None => {
+                return Err(UgoiraError::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "WebP编码结果中没有VP8L数据块",
+                )))
+            }
+        };
+        let mut anmf = Vec::new();
+        push_u24(&mut anmf, 0);
+        push_u24(&mut anmf, 0);
+        push_u24(&mut anmf, width - 1);
+        push_u24(&mut anmf, height - 1);
+        push_u24(&mut anmf, frame.delay.min(0xFFFFFF));
+        // 不与上一帧混合, 显示后不清除
+        anmf.push(0x02);
+        push_chunk(&mut anmf, b"VP8L", vp8l);
+        push_chunk(&mut chunks, b"ANMF", &anmf);
+    }
+
+    let mut out = Vec::with_capacity(chunks.len() + 12);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&((chunks.len() + 4) as u32).to_le_bytes());
+    out.extend_from_slice(b"WEBP");
+    out.extend_from_slice(&chunks);
+    Ok(out)
+}
+
+/// 把动图的帧压缩包转换成动画文件, 帧顺序和显示时间以 `frames` 为准
+pub fn convert_ugoira(
+    zip_data: &[u8],
+    frames: &[UgoiraFrame],
+    format: AnimationFormat,
+) -> Result<Vec<u8>> {
+    let decoded = decode_frames(zip_data, frames)?;
+    match format {
+        AnimationFormat::Gif => encode_gif(decoded),
+        AnimationFormat::Apng => encode_apng(decoded),
+        AnimationFormat::Webp => encode_webp(decoded),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const DELAYS: [i32; 3] = [100, 200, 50];
+
+    /// 三帧 4x4 的纯色 PNG, 文件名故意乱序写入
+    fn fixture_zip() -> Vec<u8> {
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 128]];
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for &i in &[2usize, 0, 1] {
+            let image = image::RgbaImage::from_pixel(4, 4, image::Rgba(colors[i]));
+            let mut png = Vec::new();
+            image::DynamicImage::ImageRgba8(image)
+                .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+                .unwrap();
+            writer
+                .start_file(
+                    format!("00000{}.png", i),
+                    zip::write::FileOptions::default(),
+                )
+                .unwrap();
+            writer.write_all(&png).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn fixture_frames() -> Vec<UgoiraFrame> {
+        DELAYS
+            .iter()
+            .enumerate()
+            .map(|(i, &delay)| UgoiraFrame {
+                file: format!("00000{}.png", i),
+                delay,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frames_from_zip_sorted_by_name() {
+        let frames = frames_from_zip(&fixture_zip(), 80).unwrap();
+        let names: Vec<&str> = frames.iter().map(|x| x.file.as_str()).collect();
+        assert_eq!(names, vec!["000000.png", "000001.png", "000002.png"]);
+        assert!(frames.iter().all(|x| x.delay == 80));
+    }
+
+    #[test]
+    fn gif_frame_count_and_delays() {
+        use image::AnimationDecoder;
+        let data = convert_ugoira(&fixture_zip(), &fixture_frames(), AnimationFormat::Gif).unwrap();
+        let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(data)).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        let delays: Vec<i32> = frames
+            .iter()
+            .map(|x| {
+                let (numer, denom) = x.delay().numer_denom_ms();
+                (numer / denom) as i32
+            })
+            .collect();
+        assert_eq!(delays, DELAYS.to_vec());
+        assert_eq!(frames[1].buffer().get_pixel(0, 0)[1], 255);
+    }
+
+    #[test]
+    fn apng_frame_count_and_delays() {
+        let data =
+            convert_ugoira(&fixture_zip(), &fixture_frames(), AnimationFormat::Apng).unwrap();
+        // 直接遍历 PNG 数据块: 长度(4) 类型(4) 数据 CRC(4)
+        let mut pos = 8;
+        let mut num_frames = None;
+        let mut delays = Vec::new();
+        while pos + 8 <= data.len() {
+            let len = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+                as usize;
+            let body = &data[pos + 8..pos + 8 + len];
+            match &data[pos + 4..pos + 8] {
+                b"acTL" => {
+                    num_frames = Some(u32::from_be_bytes([body[0], body[1], body[2], body[3]]))
+                }
+                b"fcTL" => {
+                    let numer = u16::from_be_bytes([body[20], body[21]]) as i32;
+                    let denom = u16::from_be_bytes([body[22], body[23]]) as i32;
+                    delays.push(numer * 1000 / denom);
+                }
+                _ => (),
+            }
+            pos += len + 12;
+        }
+        assert_eq!(num_frames, Some(3));
+        assert_eq!(delays, DELAYS.to_vec());
+        let first = image::load_from_memory(&data).unwrap().to_rgba8();
+        assert_eq!(first.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn webp_frame_count_and_delays() {
+        let data =
+            convert_ugoira(&fixture_zip(), &fixture_frames(), AnimationFormat::Webp).unwrap();
+        assert_eq!(&data[..4], b"RIFF");
+        assert_eq!(&data[8..12], b"WEBP");
+        let riff_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        assert_eq!(riff_size + 8, data.len());
+        let mut pos = 12;
+        let mut delays = Vec::new();
+        while pos + 8 <= data.len() {
+            let len =
+                u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]])
+                    as usize;
+            let body = &data[pos + 8..pos + 8 + len];
+            if &data[pos..pos + 4] == b"ANMF" {
+                delays.push(i32::from_le_bytes([body[12], body[13], body[14], 0]));
+                assert_eq!(&body[16..20], b"VP8L");
+            }
+            pos += 8 + len + len % 2;
+        }
+        assert_eq!(pos, data.len());
+        assert_eq!(delays, DELAYS.to_vec());
+    }
+
+    #[test]
+    fn missing_frame_is_an_error() {
+        let mut frames = fixture_frames();
+        frames[1].file = "missing.png".into();
+        match convert_ugoira(&fixture_zip(), &frames, AnimationFormat::Gif) {
+            Err(UgoiraError::MissingFrame(x)) => assert_eq!(x, "missing.png"),
+            x => panic!("{:?}", x.map(|x| x.len())),
+        }
+        match convert_ugoira(&fixture_zip(), &[], AnimationFormat::Gif) {
+            Err(UgoiraError::EmptyFrames) => (),
+            x => panic!("{:?}", x.map(|x| x.len())),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use super::artwork::FromError;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// 社交账号, 如 twitter/pawoo/instagram
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PixivSocialLink {
+    #[serde(rename(serialize = "service", deserialize = "service"))]
+    pub service: String,
+    #[serde(rename(serialize = "url", deserialize = "url"))]
+    pub url: String,
+}
+
+/// `ajax/user/{id}?full=1` 中的作者资料, 单独保存在 Users 集合中
+///
+/// 简介/主页/背景/社交账号可能被作者清空, 为空时也要写入 null 或 `[]` 覆盖旧资料
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PixivUserProfile {
+    #[serde(
+        rename(serialize = "_id", deserialize = "_id"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub _id: Option<ObjectId>,
+    #[serde(rename(serialize = "id", deserialize = "id"))]
+    pub user_id: i64,
+    #[serde(
+        rename(serialize = "name", deserialize = "name"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub name: Option<String>,
+    /// 个人简介 (纯文本)
+    #[serde(rename(serialize = "comment", deserialize = "comment"))]
+    pub comment: Option<String>,
+    #[serde(
+        rename(serialize = "avatar", deserialize = "avatar"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub avatar: Option<String>,
+    #[serde(
+        rename(serialize = "avatar_big", deserialize = "avatar_big"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub avatar_big: Option<String>,
+    #[serde(rename(serialize = "background", deserialize = "background"))]
+    pub background: Option<String>,
+    #[serde(rename(serialize = "webpage", deserialize = "webpage"))]
+    pub webpage: Option<String>,
+    #[serde(rename(serialize = "social", deserialize = "social"), default)]
+    pub social: Vec<PixivSocialLink>,
+    /// 关注的用户数
+    #[serde(
+        rename(serialize = "following", deserialize = "following"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub following: Option<i32>,
+    /// 粉丝数, 只有接口返回时才有
+    #[serde(
+        rename(serialize = "followers", deserialize = "followers"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub followers: Option<i32>,
+    #[serde(
+        rename(serialize = "mypixiv_count", deserialize = "mypixiv_count"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mypixiv_count: Option<i32>,
+    #[serde(
+        rename(serialize = "premium", deserialize = "premium"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub premium: Option<bool>,
+    #[serde(
+        rename(serialize = "official", deserialize = "official"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub official: Option<bool>,
+    #[serde(
+        rename(serialize = "last_update_time", deserialize = "last_update_time"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_update_time: Option<i64>,
+}
+
+fn string_value(value: &serde_json::Value, field: &str) -> Option<String> {
+    value
+        .get(field)
+        .and_then(|x| x.as_str())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+}
+
+fn count_value(value: &serde_json::Value, field: &str) -> Option<i32> {
+    value.get(field).and_then(|x| x.as_i64()).map(|x| x as i32)
+}
+
+impl TryFrom<&serde_json::Value> for PixivUserProfile {
+    type Error = FromError;
+
+    fn try_from(value: &serde_json::Value) -> Result<Self, Self::Error> {
+        let user_id = match value.get("userId").and_then(|x| x.as_str()) {
+            Some(x) => match x.parse::<i64>() {
+                Ok(x) => x,
+                Err(_) => return Err(FromError(format!("ID类型错误：{}", x))),
+            },
+            None => return Err(FromError("userId必须存在".to_string())),
+        };
+        // 没有社交账号时 social 是空数组而不是对象
+        let mut social = Vec::new();
+        if let Some(map) = value.get("social").and_then(|x| x.as_object()) {
+            for (service, link) in map {
+                if let Some(url) = link.get("url").and_then(|x| x.as_str()) {
+                    social.push(PixivSocialLink {
+                        service: service.clone(),
+                        url: url.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(PixivUserProfile {
+            _id: None,
+            user_id,
+            name: string_value(value, "name"),
+            comment: string_value(value, "comment"),
+            avatar: string_value(value, "image"),
+            avatar_big: string_value(value, "imageBig"),
+            background: value
+                .get("background")
+                .and_then(|x| x.get("url"))
+                .and_then(|x| x.as_str())
+                .map(|x| x.to_string()),
+            webpage: string_value(value, "webpage"),
+            social,
+            following: count_value(value, "following"),
+            followers: count_value(value, "followerCount"),
+            mypixiv_count: count_value(value, "mypixivCount"),
+            premium: value.get("premium").and_then(|x| x.as_bool()),
+            official: value.get("official").and_then(|x| x.as_bool()),
+            last_update_time: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleared_fields_are_serialized() {
+        let value = serde_json::json!({
+            "userId": "11",
+            "name": "pixiv事務局",
+            "comment": "",
+            "webpage": null,
+            "background": null,
+            "social": [],
+        });
+        let profile = PixivUserProfile::try_from(&value).unwrap();
+        let json = serde_json::to_value(&profile).unwrap();
+        assert_eq!(json["id"], 11);
+        assert!(json.get("comment").unwrap().is_null());
+        assert!(json.get("webpage").unwrap().is_null());
+        assert!(json.get("background").unwrap().is_null());
+        assert_eq!(json["social"], serde_json::json!([]));
+        assert!(json.get("avatar").is_none());
+    }
+
+    #[test]
+    fn social_links_are_parsed() {
+        let value = serde_json::json!({
+            "userId": "11",
+            "comment": "bio",
+            "social": {"twitter": {"url": "https://twitter.com/pixiv"}},
+        });
+        let profile = PixivUserProfile::try_from(&value).unwrap();
+        assert_eq!(profile.comment.as_deref(), Some("bio"));
+        assert_eq!(
+            profile.social,
+            vec![PixivSocialLink {
+                service: "twitter".to_string(),
+                url: "https://twitter.com/pixiv".to_string(),
+            }]
+        );
+        assert!(PixivUserProfile::try_from(&serde_json::json!({})).is_err());
+    }
+}
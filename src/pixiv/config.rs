@@ -1,3 +1,7 @@
+use super::base::{
+    AccountConfig, CassetteMode, ProxyPoolConfig, RateLimitConfig, RetryPolicy, SidecarFormat,
+    ThumbnailFormat,
+};
 use serde::Deserialize;
 
 #[derive(Deserialize)]
@@ -8,9 +12,160 @@ pub struct GlobalConfig {
     pub proxy: String,
     pub pixiv_cookie: String,
     pub search_config_path: String,
+    /// 排行榜抓取配置文件, 不设置则不抓取排行榜
+    #[serde(default)]
+    pub ranking_config_path: Option<String>,
     pub search_thread_num: u32,
     pub user_detail_thread_num: u32,
     pub update_artwork_thread_num: u32,
+    #[serde(default)]
+    pub pixiv_host: Option<String>,
+    #[serde(default)]
+    pub cassette: Option<CassetteConfig>,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// 配置后代替 `proxy`
+    #[serde(default)]
+    pub proxy_pool: Option<ProxyPoolConfig>,
+    /// 配置后代替 `pixiv_cookie`
+    #[serde(default)]
+    pub accounts: Vec<AccountConfig>,
+    /// 不设置则不下载原图
+    #[serde(default)]
+    pub download: Option<DownloadConfig>,
+    /// 不设置则不生成缩略图
+    #[serde(default)]
+    pub thumbnail: Option<ThumbnailConfig>,
+    /// 不设置则不抓取小说
+    #[serde(default)]
+    pub novel: Option<NovelConfig>,
+    /// 不设置则不抓取关注列表
+    #[serde(default)]
+    pub following: Option<FollowingConfig>,
+    /// 不设置则不抓取作品所属的系列
+    #[serde(default)]
+    pub series: Option<SeriesConfig>,
+    /// 不设置则不抓取作者资料
+    #[serde(default)]
+    pub users: Option<UsersConfig>,
+}
+
+#[derive(Deserialize)]
+pub struct UsersConfig {
+    /// 作者资料的刷新间隔 (小时), 抓取失败的作者也等到下一次刷新
+    #[serde(default = "default_user_refresh_hours")]
+    pub refresh_hours: u64,
+}
+
+fn default_user_refresh_hours() -> u64 {
+    24
+}
+
+#[derive(Deserialize)]
+pub struct SeriesConfig {
+    /// 系列的刷新间隔 (小时), 抓取失败的系列也等到下一次刷新
+    #[serde(default = "default_series_refresh_hours")]
+    pub refresh_hours: u64,
+}
+
+fn default_series_refresh_hours() -> u64 {
+    24
+}
+
+#[derive(Deserialize)]
+pub struct FollowingConfig {
+    /// 要遍历关注列表的用户, 为空时使用当前登录账号
+    #[serde(default)]
+    pub user_ids: Vec<i64>,
+    /// 同时抓取登录账号的非公开关注
+    #[serde(default)]
+    pub include_private: bool,
+    #[serde(default = "default_following_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_following_interval_secs() -> u64 {
+    3600
+}
+
+#[derive(Deserialize)]
+pub struct NovelConfig {
+    /// 按标签搜索小说
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default = "default_novel_sort")]
+    pub sort: String,
+    #[serde(default = "default_novel_max_page")]
+    pub max_page: u32,
+    /// 同时抓取已收录插画作者的小说
+    #[serde(default)]
+    pub from_authors: bool,
+    #[serde(default = "default_novel_thread_num")]
+    pub thread_num: u32,
+}
+
+fn default_novel_sort() -> String {
+    "date_d".to_string()
+}
+
+fn default_novel_max_page() -> u32 {
+    10
+}
+
+fn default_novel_thread_num() -> u32 {
+    2
+}
+
+#[derive(Deserialize)]
+pub struct ThumbnailConfig {
+    /// 缩略图目录, 不设置则与原图放在一起
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// 长边尺寸列表
+    #[serde(default = "default_thumbnail_sizes")]
+    pub sizes: Vec<u32>,
+    #[serde(default = "default_thumbnail_format")]
+    pub format: ThumbnailFormat,
+}
+
+fn default_thumbnail_sizes() -> Vec<u32> {
+    vec![256, 1024]
+}
+
+fn default_thumbnail_format() -> ThumbnailFormat {
+    ThumbnailFormat::Jpeg
+}
+
+#[derive(Deserialize)]
+pub struct DownloadConfig {
+    pub dir: String,
+    /// 相对 `dir` 的保存路径, 支持 `{user_id}` `{artwork_id}` `{n}` `{ext}`
+    #[serde(default = "default_download_layout")]
+    pub layout: String,
+    #[serde(default = "default_download_thread_num")]
+    pub thread_num: u32,
+    /// 把标题/作者/标签/作品链接写入图片文件 (JPEG 的 EXIF/XMP, PNG 的 iTXt)
+    #[serde(default)]
+    pub embed_metadata: bool,
+    /// 在图片旁写入的附属文件格式, 可以同时写多种
+    #[serde(default)]
+    pub sidecars: Vec<SidecarFormat>,
+}
+
+fn default_download_layout() -> String {
+    "{user_id}/{artwork_id}_p{n}.{ext}".to_string()
+}
+
+fn default_download_thread_num() -> u32 {
+    4
+}
+
+#[derive(Deserialize)]
+pub struct CassetteConfig {
+    pub mode: CassetteMode,
+    pub dir: String,
 }
 
 lazy_static::lazy_static! {
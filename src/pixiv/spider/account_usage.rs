@@ -0,0 +1,67 @@
+use super::super::base::AccountUsage;
+use super::GlobalConfig;
+use futures::StreamExt;
+use log::{error, info};
+use mongodb::{bson::doc, Collection};
+use std::sync::Arc;
+
+/// 启动时从数据库恢复账号池的用量, 需要在爬虫创建客户端之前调用
+pub async fn restore(config: Arc<GlobalConfig>, collection: Collection) {
+    if config.accounts.is_empty() {
+        return;
+    }
+    let cursor = match collection.find(None, None).await {
+        Ok(x) => x,
+        Err(e) => {
+            error!("读取账号用量失败: {:?}", e);
+            return;
+        }
+    };
+    let records: Vec<AccountUsage> = cursor
+        .filter_map(|x| async move {
+            match x {
+                Ok(x) => mongodb::bson::from_document(x).ok(),
+                Err(_) => None,
+            }
+        })
+        .collect()
+        .await;
+    super::shared_account_pool(&config).restore_usage(&records);
+}
+
+/// 定时把账号池中每个账号的用量写入数据库, 每个账号每天一条记录
+///
+/// 今日请求数用 `$inc` 累加, 多个进程或重启前后的计数不会互相覆盖
+pub async fn run(config: Arc<GlobalConfig>, collection: Collection) {
+    if config.accounts.is_empty() {
+        return;
+    }
+    let pool = super::shared_account_pool(&config);
+    loop {
+        async_std::task::sleep(std::time::Duration::from_secs(60)).await;
+        let unsaved = pool.take_unsaved();
+        for (usage, count) in pool.usage().into_iter().zip(unsaved) {
+            info!(
+                "账号 {} 今日请求 {} 次, 累计 {} 次{}",
+                usage.name,
+                usage.requests_today,
+                usage.requests_total,
+                if usage.retired { " (已失效)" } else { "" }
+            );
+            let mut options = mongodb::options::UpdateOptions::default();
+            options.upsert = Some(true);
+            collection
+                .update_one(
+                    doc! {"name" : &usage.name, "date" : &usage.date},
+                    doc! {
+                        "$inc" : {"requests_today" : count as i64},
+                        "$max" : {"requests_total" : usage.requests_total},
+                        "$set" : {"retired" : usage.retired},
+                    },
+                    options,
+                )
+                .await
+                .unwrap();
+        }
+    }
+}
@@ -1,15 +1,18 @@
 use super::GlobalConfig;
 use super::{
-    super::base::{Artwork, PixivError},
-    AsyncQueue, RunnerContext, StreamWrapper,
+    super::base::{Artwork, ArtworkType, PixivError},
+    AsyncQueue, RunnerContext, StreamWrapper, MAX_TASK_ATTEMPTS,
 };
 use futures::{stream::select_all, StreamExt};
-use log::{error, info};
+use log::{error, info, warn};
 use mongodb::{bson::doc, bson::Document, Collection};
 use std::sync::Arc;
-struct UpdateArtworkTask {
+#[derive(Debug)]
+pub struct UpdateArtworkTask {
     artwork_id: i64,
     is_new: bool,
+    /// 已经因为可重试的错误失败的次数
+    attempts: u32,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -20,6 +23,8 @@ pub enum Error {
     OtherError(#[from] PixivError),
     #[error("{0:?} 作品不存在或被删除")]
     ArtworkNotExists(i64, bool),
+    #[error("抓取作品-稍后重试")]
+    Retry(PixivError, UpdateArtworkTask),
 }
 
 async fn query_documents(
@@ -58,6 +63,7 @@ async fn load_tasks(collection: &mut Collection, cache_size: usize) -> Vec<Updat
         cache.push(UpdateArtworkTask {
             artwork_id,
             is_new: true,
+            attempts: 0,
         });
     });
     if cache.len() < cache_size {
@@ -84,6 +90,7 @@ async fn load_tasks(collection: &mut Collection, cache_size: usize) -> Vec<Updat
             cache.push(UpdateArtworkTask {
                 artwork_id,
                 is_new: false,
+                attempts: 0,
             });
         });
     };
@@ -100,11 +107,36 @@ async fn fetch_artwork(
         Some(x) => x,
         None => return (Err(Error::EmptyQueue), ctx),
     };
-    match ctx.client.load_artwork(t.artwork_id).await {
+    let result = match ctx.client.load_artwork(t.artwork_id).await {
+        Ok(mut x) if x.page_count.unwrap_or(1) > 1 => {
+            match ctx.client.load_pages(t.artwork_id).await {
+                Ok(pages) => {
+                    x.pages = pages;
+                    Ok(x)
+                }
+                Err(e) => Err(e),
+            }
+        }
+        x => x,
+    };
+    let result = match result {
+        Ok(mut x) if x.artwork_type == Some(ArtworkType::Ugoira) => {
+            match ctx.client.load_ugoira_meta(t.artwork_id).await {
+                Ok(meta) => {
+                    x.ugoira = Some(meta);
+                    Ok(x)
+                }
+                Err(e) => Err(e),
+            }
+        }
+        x => x,
+    };
+    match result {
         Ok(x) => (Ok((x, t.is_new)), ctx),
         Err(PixivError::ArtworkNotExists(_)) => {
             (Err(Error::ArtworkNotExists(t.artwork_id, t.is_new)), ctx)
         }
+        Err(e) if e.is_retryable() => (Err(Error::Retry(e, t)), ctx),
         Err(e) => (Err(Error::OtherError(e)), ctx),
     }
 }
@@ -131,7 +163,22 @@ pub async fn run(config: Arc<GlobalConfig>, mut collection: Collection) {
 
         match selector.next().await.unwrap() {
             Err(Error::EmptyQueue) => info!("EmptyQueue"),
+            Err(Error::OtherError(PixivError::SessionExpired(x))) => {
+                error!("{} 登录已失效,作品未保存,请更新 pixiv_cookie", x)
+            }
             Err(Error::OtherError(x)) => error!("{:?}", x),
+            Err(Error::Retry(e, mut task)) => {
+                task.attempts += 1;
+                if task.attempts >= MAX_TASK_ATTEMPTS {
+                    warn!(
+                        "{} 已失败 {} 次,本轮不再重试: {}",
+                        task.artwork_id, task.attempts, e
+                    );
+                } else {
+                    warn!("{} 重试后仍然失败,放回队列末尾: {}", task.artwork_id, e);
+                    cache.push(task).await;
+                }
+            }
             Err(Error::ArtworkNotExists(artwork_id, is_new)) => {
                 if is_new {
                     collection
@@ -0,0 +1,426 @@
+use super::super::base::{
+    embed_metadata, write_sidecar, Artwork, EmbedError, EmbeddedMetadata, PixivError, PixivFile,
+};
+use super::super::config::{DownloadConfig, GlobalConfig};
+use super::stream_wrapper::{AsyncQueue, RunnerContext, StreamWrapper};
+use futures::StreamExt;
+use log::{error, info};
+use mongodb::{bson::doc, bson::Document, Collection};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+struct DownloadTask {
+    artwork_id: i64,
+    user_id: i64,
+    /// 按页码顺序的原图地址
+    urls: Vec<String>,
+    metadata: EmbeddedMetadata,
+    artwork: Arc<Artwork>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("")]
+    EmptyQueue,
+    #[error("下载原图-网络错误 {0}")]
+    Pixiv(i64, PixivError),
+    #[error("下载原图-IO错误 {0}")]
+    Io(i64, std::io::Error),
+    #[error("写入作品信息错误 {0}")]
+    Embed(i64, EmbedError),
+}
+
+impl Error {
+    fn artwork_id(&self) -> Option<i64> {
+        match self {
+            Error::EmptyQueue => None,
+            Error::Pixiv(x, _) | Error::Io(x, _) | Error::Embed(x, _) => Some(*x),
+        }
+    }
+}
+
+/// 同一个作品最多尝试下载的次数, 原图被删除 (404) 等永久错误不会无限重试
+const MAX_DOWNLOAD_ATTEMPTS: i32 = 3;
+
+fn task_from_artwork(artwork: Artwork) -> Option<DownloadTask> {
+    let user_id = artwork.user.as_ref()?.user_id?;
+    let metadata = EmbeddedMetadata::from_artwork(&artwork);
+    let urls: Vec<String> = if artwork.pages.is_empty() {
+        vec![artwork.image_urls.as_ref()?.large.clone()?]
+    } else {
+        artwork
+            .pages
+            .iter()
+            .filter_map(|x| x.large.clone())
+            .collect()
+    };
+    if urls.is_empty() {
+        return None;
+    }
+    Some(DownloadTask {
+        artwork_id: artwork.artwork_id,
+        user_id,
+        urls,
+        metadata,
+        artwork: Arc::new(artwork),
+    })
+}
+
+/// 把查询到的文档转换为下载任务, 无法解析或没有可用原图地址的作品id放在第二个返回值中
+fn tasks_from_documents(documents: Vec<Document>) -> (Vec<DownloadTask>, Vec<i64>) {
+    let mut tasks = Vec::new();
+    let mut skipped = Vec::new();
+    for document in documents {
+        let artwork_id = document.get_i64("id").ok();
+        let task = mongodb::bson::from_document::<Artwork>(document)
+            .ok()
+            .and_then(task_from_artwork);
+        match (task, artwork_id) {
+            (Some(x), _) => tasks.push(x),
+            (None, Some(x)) => skipped.push(x),
+            (None, None) => (),
+        }
+    }
+    (tasks, skipped)
+}
+
+/// `in_flight` 为正在下载的作品, 下载完成前 `files` 还不存在, 需要排除避免重复下载同一个文件
+async fn load_tasks(
+    collection: &mut Collection,
+    cache_size: usize,
+    in_flight: &HashSet<i64>,
+) -> Vec<DownloadTask> {
+    let in_flight: Vec<i64> = in_flight.iter().cloned().collect();
+    let cursor = collection
+        .aggregate(
+            vec![
+                doc! {"$match" : {
+                    "id" : {"$nin" : in_flight},
+                    "image_urls.large" : {"$exists" : 1},
+                    "files" : {"$exists" : 0},
+                    "download_attempts" : {"$not" : {"$gte" : MAX_DOWNLOAD_ATTEMPTS}},
+                }},
+                doc! {"$limit" : cache_size as i64},
+            ],
+            None,
+        )
+        .await
+        .unwrap();
+    let documents: Vec<Document> = cursor.filter_map(|x| async move { x.ok() }).collect().await;
+    let (tasks, skipped) = tasks_from_documents(documents);
+    // 无法下载的文档直接记为达到重试上限, 否则会一直占据查询结果
+    if !skipped.is_empty() {
+        error!("{} 个作品无法解析或缺少原图地址, 不再下载", skipped.len());
+        collection
+            .update_many(
+                doc! {"id" : {"$in" : skipped}},
+                doc! {"$set" : {"download_attempts" : MAX_DOWNLOAD_ATTEMPTS}},
+                None,
+            )
+            .await
+            .unwrap();
+    }
+    tasks
+}
+
+fn file_ext(url: &str) -> &str {
+    let name = url.rsplit('/').next().unwrap_or(url);
+    let name = name.split('?').next().unwrap_or(name);
+    match name.rfind('.') {
+        Some(pos) => &name[pos + 1..],
+        None => "jpg",
+    }
+}
+
+fn local_path(config: &DownloadConfig, task: &DownloadTask, page: usize, url: &str) -> PathBuf {
+    let relative = config
+        .layout
+        .replace("{user_id}", &task.user_id.to_string())
+        .replace("{artwork_id}", &task.artwork_id.to_string())
+        .replace("{n}", &page.to_string())
+        .replace("{ext}", file_ext(url));
+    Path::new(&config.dir).join(relative)
+}
+
+/// 分块读取文件计算 SHA-256, 不把整个文件读进内存
+async fn file_digest(path: &Path) -> std::io::Result<(u64, String)> {
+    use futures::AsyncReadExt;
+    let mut file = async_std::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut size: u64 = 0;
+    loop {
+        let n = file.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        size += n as u64;
+    }
+    Ok((size, hex::encode(hasher.finalize())))
+}
+
+/// 下载一页原图; 最终路径已经存在说明之前下载完整 (先写临时文件再改名), 直接跳过
+///
+/// 设置了 `metadata` 时把作品信息写入文件, 摘要按写入后的文件计算
+async fn download_page(
+    ctx: &mut RunnerContext<DownloadTask>,
+    artwork_id: i64,
+    path: &Path,
+    url: &str,
+    metadata: Option<&EmbeddedMetadata>,
+) -> Result<(u64, String), Error> {
+    if async_std::fs::metadata(path).await.is_err() {
+        if let Err(e) = ctx.client.download_image_to(url, path).await {
+            return Err(Error::Pixiv(artwork_id, e));
+        }
+    }
+    if let Some(metadata) = metadata {
+        let path = path.to_path_buf();
+        let metadata = metadata.clone();
+        if let Err(e) =
+            async_std::task::spawn_blocking(move || embed_metadata(&path, &metadata)).await
+        {
+            return Err(Error::Embed(artwork_id, e));
+        }
+    }
+    match file_digest(path).await {
+        Ok(x) => Ok(x),
+        Err(e) => Err(Error::Io(artwork_id, e)),
+    }
+}
+
+async fn download_artwork(
+    mut ctx: RunnerContext<DownloadTask>,
+    config: Arc<GlobalConfig>,
+) -> (
+    Result<(i64, Vec<PixivFile>), Error>,
+    RunnerContext<DownloadTask>,
+) {
+    let task = match ctx.queue.pop().await {
+        Some(x) => x,
+        None => return (Err(Error::EmptyQueue), ctx),
+    };
+    let download_config = config.download.as_ref().unwrap();
+    let metadata = if download_config.embed_metadata {
+        Some(&task.metadata)
+    } else {
+        None
+    };
+    let mut files = Vec::new();
+    for (page, url) in task.urls.iter().enumerate() {
+        let path = local_path(download_config, &task, page, url);
+        let (size, sha256) =
+            match download_page(&mut ctx, task.artwork_id, &path, url, metadata).await {
+                Ok(x) => x,
+                Err(e) => return (Err(e), ctx),
+            };
+        for &format in &download_config.sidecars {
+            let artwork = task.artwork.clone();
+            let url = url.clone();
+            let path = path.clone();
+            let result = async_std::task::spawn_blocking(move || {
+                write_sidecar(&artwork, page, &url, &path, format)
+            })
+            .await;
+            if let Err(e) = result {
+                return (Err(Error::Io(task.artwork_id, e)), ctx);
+            }
+        }
+        files.push(PixivFile {
+            page: page as i32,
+            url: url.clone(),
+            path: path.to_string_lossy().to_string(),
+            size: size as i64,
+            sha256,
+            thumbnails: Vec::new(),
+            phash: None,
+        });
+    }
+    (Ok((task.artwork_id, files)), ctx)
+}
+
+pub async fn run(config: Arc<GlobalConfig>, mut collection: Collection) {
+    let thread_num = match config.download {
+        Some(ref x) => x.thread_num,
+        None => return,
+    };
+    let queue: Arc<AsyncQueue<DownloadTask>> = Arc::new(AsyncQueue::new());
+    let mut streams = Vec::new();
+    for _ in 0..thread_num {
+        let future_config = config.clone();
+        streams.push(StreamWrapper::new(
+            RunnerContext {
+                queue: queue.clone(),
+                client: super::new_client(config.clone()).unwrap(),
+            },
+            move |ctx| download_artwork(ctx, future_config.clone()),
+        ));
+    }
+    let mut selector = futures::stream::select_all(streams);
+    let mut total_count = 0;
+    // 已经放入队列还没有结果的作品
+    let mut in_flight: HashSet<i64> = HashSet::new();
+    loop {
+        if queue.size().await == 0 {
+            let tasks = load_tasks(&mut collection, 1000, &in_flight).await;
+            if tasks.is_empty() {
+                async_std::task::sleep(std::time::Duration::from_secs(60)).await;
+                continue;
+            }
+            in_flight.extend(tasks.iter().map(|x| x.artwork_id));
+            queue.push_all(tasks).await;
+        }
+        match selector.next().await.unwrap() {
+            Err(Error::EmptyQueue) => (),
+            Err(e) => {
+                error!("{:?}", e);
+                if let Some(artwork_id) = e.artwork_id() {
+                    in_flight.remove(&artwork_id);
+                    collection
+                        .update_one(
+                            doc! {"id" : artwork_id},
+                            doc! {"$inc" : {"download_attempts" : 1}},
+                            None,
+                        )
+                        .await
+                        .unwrap();
+                }
+            }
+            Ok((artwork_id, files)) => {
+                in_flight.remove(&artwork_id);
+                let files = files
+                    .iter()
+                    .map(|x| mongodb::bson::to_document(x).unwrap())
+                    .collect::<Vec<_>>();
+                collection
+                    .update_one(
+                        doc! {"id" : artwork_id},
+                        doc! {"$set" : {"files" : files}},
+                        None,
+                    )
+                    .await
+                    .unwrap();
+                total_count += 1;
+                if total_count % 100 == 0 {
+                    info!("下载了 {} 个作品的原图", total_count);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    const ORIGINAL: &str =
+        "https://i.pximg.net/img-original/img/2020/12/01/00/00/00/84000001_p0.png";
+
+    fn artwork(value: serde_json::Value) -> Artwork {
+        Artwork::try_from(&value).unwrap()
+    }
+
+    #[test]
+    fn file_ext_from_url() {
+        assert_eq!(file_ext(ORIGINAL), "png");
+        assert_eq!(
+            file_ext("https://i.pximg.net/a/84000001_p1.jpg?1606780800"),
+            "jpg"
+        );
+        assert_eq!(file_ext("https://i.pximg.net/a.b/84000001_p1"), "jpg");
+    }
+
+    #[test]
+    fn task_from_single_and_multi_page() {
+        let single = artwork(serde_json::json!({
+            "id": "84000001",
+            "userId": "11",
+            "urls": {"original": ORIGINAL},
+        }));
+        let task = task_from_artwork(single).unwrap();
+        assert_eq!(task.artwork_id, 84000001);
+        assert_eq!(task.user_id, 11);
+        assert_eq!(task.urls, vec![ORIGINAL.to_string()]);
+
+        let mut multi = artwork(serde_json::json!({
+            "id": "84000001",
+            "userId": "11",
+            "urls": {"original": ORIGINAL},
+        }));
+        let mut pages: Vec<serde_json::Value> = (0..3)
+            .map(|n| serde_json::json!({
+                "large": format!("https://i.pximg.net/img-original/img/2020/12/01/00/00/00/84000001_p{}.jpg", n),
+            }))
+            .collect();
+        // 缺少原图地址的页跳过
+        pages.push(serde_json::json!({}));
+        multi.pages = serde_json::from_value(serde_json::Value::Array(pages)).unwrap();
+        let task = task_from_artwork(multi).unwrap();
+        assert_eq!(task.urls.len(), 3);
+        assert!(task.urls[2].ends_with("84000001_p2.jpg"));
+    }
+
+    #[test]
+    fn task_rejects_missing_user_or_urls() {
+        let no_user = artwork(serde_json::json!({"id": "1", "urls": {"original": ORIGINAL}}));
+        assert!(task_from_artwork(no_user).is_none());
+        let no_urls = artwork(serde_json::json!({"id": "1", "userId": "11"}));
+        assert!(task_from_artwork(no_urls).is_none());
+        let mut empty_pages = artwork(serde_json::json!({
+            "id": "1",
+            "userId": "11",
+            "urls": {"original": ORIGINAL},
+        }));
+        empty_pages.pages = serde_json::from_value(serde_json::json!([{}])).unwrap();
+        assert!(task_from_artwork(empty_pages).is_none());
+    }
+
+    #[test]
+    fn unusable_documents_are_skipped() {
+        let ok = artwork(serde_json::json!({
+            "id": "1",
+            "userId": "11",
+            "urls": {"original": ORIGINAL},
+        }));
+        let no_user = artwork(serde_json::json!({"id": "2", "urls": {"original": ORIGINAL}}));
+        let documents = vec![
+            mongodb::bson::to_document(&ok).unwrap(),
+            mongodb::bson::to_document(&no_user).unwrap(),
+            doc! {"id" : 3_i64, "title" : 1},
+            doc! {"title" : "没有id"},
+        ];
+        let (tasks, skipped) = tasks_from_documents(documents);
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].artwork_id, 1);
+        assert_eq!(skipped, vec![2, 3]);
+    }
+
+    #[test]
+    fn local_path_from_layout() {
+        let config: DownloadConfig =
+            serde_json::from_value(serde_json::json!({"dir": "/data/pixiv"})).unwrap();
+        let task = task_from_artwork(artwork(serde_json::json!({
+            "id": "84000001",
+            "userId": "11",
+            "urls": {"original": ORIGINAL},
+        })))
+        .unwrap();
+        assert_eq!(
+            local_path(&config, &task, 2, ORIGINAL),
+            Path::new("/data/pixiv/11/84000001_p2.png")
+        );
+        let config: DownloadConfig = serde_json::from_value(serde_json::json!({
+            "dir": "/data",
+            "layout": "{artwork_id}/{n}.{ext}",
+        }))
+        .unwrap();
+        assert_eq!(
+            local_path(&config, &task, 0, "https://i.pximg.net/x/84000001_p0.jpg"),
+            Path::new("/data/84000001/0.jpg")
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use super::super::base::Artwork;
+use futures::StreamExt;
+use log::{error, info};
+use mongodb::{
+    bson::{doc, Bson, Document},
+    Collection,
+};
+use std::path::PathBuf;
+
+/// 把查询到的文档转换为作品, 无法解析的文档的 `_id` 放在第二个返回值中
+fn artworks_from_documents(documents: Vec<Document>) -> (Vec<Artwork>, Vec<Bson>) {
+    let mut artworks = Vec::new();
+    let mut skipped = Vec::new();
+    for document in documents {
+        let object_id = document.get("_id").cloned();
+        match (mongodb::bson::from_document::<Artwork>(document), object_id) {
+            (Ok(x), _) => artworks.push(x),
+            (Err(_), Some(x)) => skipped.push(x),
+            (Err(_), None) => (),
+        }
+    }
+    (artworks, skipped)
+}
+
+/// 取出已下载原图但还没有 `files.<页>.<field>` 的作品
+///
+/// 无法解析的文档直接标记 `failed_field`, 否则会一直占据 `$limit` 的查询结果
+async fn load_artworks(
+    collection: &mut Collection,
+    field: &str,
+    failed_field: &str,
+    cache_size: usize,
+) -> Vec<Artwork> {
+    let mut filter = doc! {"files" : {"$exists" : 1}};
+    filter.insert(format!("files.{}", field), doc! {"$exists" : 0});
+    filter.insert(failed_field, doc! {"$exists" : 0});
+    let cursor = collection
+        .aggregate(
+            vec![
+                doc! {"$match" : filter},
+                doc! {"$limit" : cache_size as i64},
+            ],
+            None,
+        )
+        .await
+        .unwrap();
+    let documents: Vec<Document> = cursor.filter_map(|x| async move { x.ok() }).collect().await;
+    let (artworks, skipped) = artworks_from_documents(documents);
+    if !skipped.is_empty() {
+        error!("{} 个作品无法解析, 标记为 {}", skipped.len(), failed_field);
+        let mut update = Document::new();
+        update.insert(failed_field, true);
+        collection
+            .update_many(
+                doc! {"_id" : {"$in" : skipped}},
+                doc! {"$set" : update},
+                None,
+            )
+            .await
+            .unwrap();
+    }
+    artworks
+}
+
+/// 逐页处理已下载原图的后台任务 (缩略图、感知哈希等)
+///
+/// `process` 在阻塞线程池中执行, 结果写入 `files.<页>.<field>`;
+/// 任意一页失败 (文件损坏或丢失) 时只写入 `failed_field` 标记, 避免反复重试
+pub async fn run<F, E>(
+    mut collection: Collection,
+    field: &str,
+    failed_field: &str,
+    description: &str,
+    process: F,
+) where
+    F: Fn(PathBuf, i64, i32) -> Result<Bson, E> + Clone + Send + 'static,
+    E: std::fmt::Debug + Send + 'static,
+{
+    let mut total_count = 0;
+    loop {
+        let artworks = load_artworks(&mut collection, field, failed_field, 100).await;
+        if artworks.is_empty() {
+            async_std::task::sleep(std::time::Duration::from_secs(60)).await;
+            continue;
+        }
+        for artwork in artworks {
+            // 只更新 `files.<页>.<field>`, 避免覆盖其他任务写入的字段
+            let mut update = Document::new();
+            for (index, file) in artwork.files.iter().enumerate() {
+                let source = PathBuf::from(&file.path);
+                let (artwork_id, page) = (artwork.artwork_id, file.page);
+                let process = process.clone();
+                // 解码、缩放图片是 CPU 密集操作, 放到阻塞线程池中执行
+                match async_std::task::spawn_blocking(move || process(source, artwork_id, page))
+                    .await
+                {
+                    Ok(x) => {
+                        update.insert(format!("files.{}.{}", index, field), x);
+                    }
+                    Err(e) => {
+                        error!("{} {}失败: {:?}", file.path, description, e);
+                        update.clear();
+                        break;
+                    }
+                }
+            }
+            if update.is_empty() {
+                update.insert(failed_field, true);
+            }
+            collection
+                .update_one(
+                    doc! {"id" : artwork.artwork_id},
+                    doc! {"$set" : update},
+                    None,
+                )
+                .await
+                .unwrap();
+            total_count += 1;
+            if total_count % 100 == 0 {
+                info!("{} 个作品完成{}", total_count, description);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::oid::ObjectId;
+
+    #[test]
+    fn unparseable_documents_are_skipped() {
+        let ok = doc! {
+            "_id" : ObjectId::new(),
+            "id" : 84000001_i64,
+            "files" : [{"page" : 0, "url" : "u", "path" : "p", "size" : 1_i64, "sha256" : "x"}],
+        };
+        let bad_id = ObjectId::new();
+        let documents = vec![
+            ok,
+            doc! {"_id" : bad_id.clone(), "id" : 2_i64, "files" : "不是数组"},
+            doc! {"id" : 3_i64, "files" : 1},
+        ];
+        let (artworks, skipped) = artworks_from_documents(documents);
+        assert_eq!(artworks.len(), 1);
+        assert_eq!(artworks[0].artwork_id, 84000001);
+        assert_eq!(artworks[0].files[0].path, "p");
+        assert_eq!(skipped, vec![Bson::ObjectId(bad_id)]);
+    }
+}
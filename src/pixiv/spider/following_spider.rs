@@ -0,0 +1,154 @@
+use super::super::base::PixivUser;
+use super::{GlobalConfig, PixivClient};
+use futures::StreamExt;
+use log::{error, info};
+use mongodb::{bson::doc, Collection};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// 每页的作者数, 与网页版一致
+const PAGE_SIZE: u32 = 24;
+
+async fn crawl_following(client: &mut PixivClient, user_id: i64, rest: &str) -> Vec<PixivUser> {
+    let mut users = Vec::new();
+    let mut offset = 0;
+    loop {
+        match client.following(user_id, offset, PAGE_SIZE, rest).await {
+            Ok((total, page)) => {
+                let l = page.len() as u32;
+                users.extend(page);
+                offset += PAGE_SIZE;
+                if l == 0 || offset >= total {
+                    break;
+                }
+            }
+            Err(e) => {
+                error!("{:?}", e);
+                break;
+            }
+        }
+    }
+    users
+}
+
+/// 新关注的作者立即抓取作品列表, 不等 authors_spider 从已有作品中发现; 成功时返回 true
+async fn crawl_creator(client: &mut PixivClient, artworks: &Collection, user: &PixivUser) -> bool {
+    let user_id = user.user_id.unwrap();
+    let ids = match client.load_by_creator(user_id).await {
+        Ok(x) => x,
+        Err(e) => {
+            error!("{:?}", e);
+            return false;
+        }
+    };
+    let mut inserted_count: usize = 0;
+    for _id in &ids {
+        let mut options = mongodb::options::UpdateOptions::default();
+        options.upsert = Some(true);
+        let update_result = artworks
+            .update_one(doc! {"id":_id}, doc! {"$set":{"id":_id}}, options)
+            .await
+            .unwrap();
+        if update_result.upserted_id.is_some() {
+            inserted_count += 1;
+        }
+    }
+    info!(
+        "关注的作者 {}-{:?} 共 {} 个作品,新增了 {} 个作品",
+        user_id,
+        user.name,
+        ids.len(),
+        inserted_count
+    );
+    true
+}
+
+/// 关注的作者中还没有成功抓取过作品列表 (`seeded` 不为 true) 的作者
+async fn load_unseeded(users: &Collection, ids: Vec<i64>) -> HashSet<i64> {
+    users
+        .find(doc! {"id" : {"$in" : ids}, "seeded" : {"$ne" : true}}, None)
+        .await
+        .unwrap()
+        .filter_map(|x| async move { x.ok()?.get_i64("id").ok() })
+        .collect()
+        .await
+}
+
+/// 遍历关注列表, 把作者写入 `users` (由 users_spider 抓取资料), 新作者的作品写入 `artworks`
+///
+/// 作品列表抓取成功后标记 `seeded`, 失败的作者在下一轮重新抓取
+pub async fn run(config: Arc<GlobalConfig>, artworks: Collection, users: Collection) {
+    let following_config = match config.following {
+        Some(ref x) => x,
+        None => return,
+    };
+    let mut client = super::new_client(config.clone()).unwrap();
+    // 当前登录账号, 成功取得后不再请求
+    let mut self_id: Option<i64> = None;
+    loop {
+        if self_id.is_none()
+            && (following_config.user_ids.is_empty() || following_config.include_private)
+        {
+            match client.load_self_id().await {
+                Ok(x) => self_id = Some(x),
+                Err(e) => error!("{:?}", e),
+            }
+        }
+        let user_ids = if following_config.user_ids.is_empty() {
+            self_id.into_iter().collect()
+        } else {
+            following_config.user_ids.clone()
+        };
+        for user_id in user_ids {
+            let mut following = crawl_following(&mut client, user_id, "show").await;
+            // 非公开关注只能查看登录账号自己的
+            if following_config.include_private && self_id == Some(user_id) {
+                following.extend(crawl_following(&mut client, user_id, "hide").await);
+            }
+            let mut new_count = 0;
+            for user in &following {
+                let followed_id = user.user_id.unwrap();
+                let mut options = mongodb::options::UpdateOptions::default();
+                options.upsert = Some(true);
+                let update_result = users
+                    .update_one(
+                        doc! {"id" : followed_id},
+                        doc! {"$addToSet" : {"followed_by" : user_id}},
+                        options,
+                    )
+                    .await
+                    .unwrap();
+                if update_result.upserted_id.is_some() {
+                    new_count += 1;
+                }
+            }
+            info!(
+                "用户 {} 共关注了 {} 个作者, 新增了 {}",
+                user_id,
+                following.len(),
+                new_count
+            );
+            let unseeded =
+                load_unseeded(&users, following.iter().filter_map(|x| x.user_id).collect()).await;
+            for user in following
+                .iter()
+                .filter(|x| unseeded.contains(&x.user_id.unwrap()))
+            {
+                if crawl_creator(&mut client, &artworks, user).await {
+                    users
+                        .update_one(
+                            doc! {"id" : user.user_id.unwrap()},
+                            doc! {"$set" : {"seeded" : true}},
+                            None,
+                        )
+                        .await
+                        .unwrap();
+                }
+            }
+        }
+        async_std::task::sleep(std::time::Duration::from_secs(
+            following_config.interval_secs,
+        ))
+        .await;
+    }
+}
@@ -1,19 +1,89 @@
+pub mod account_usage;
 pub mod artworks_spider;
 pub mod authors_spider;
+pub mod download_spider;
+mod file_tasks;
+pub mod following_spider;
+pub mod novels_spider;
+pub mod phash_spider;
+pub mod ranking_spider;
+pub mod series_spider;
 pub mod stream_wrapper;
 pub mod tags_spider;
+pub mod thumbnail_spider;
+pub mod users_spider;
 pub use super::base::{Artwork, PixivClient, PixivClientOption, PixivError, PixivUser};
 pub use super::config::GlobalConfig;
 pub use stream_wrapper::{AsyncQueue, RunnerContext, StreamWrapper};
 
+/// 可重试的错误最多放回队列的次数, 超过后留到下一轮再抓取
+const MAX_TASK_ATTEMPTS: u32 = 3;
+
+lazy_static::lazy_static! {
+    static ref RATE_LIMITER: std::sync::Mutex<Option<std::sync::Arc<super::base::RateLimiter>>> =
+        std::sync::Mutex::new(None);
+    static ref ACCOUNT_POOL: std::sync::Mutex<Option<std::sync::Arc<super::base::AccountPool>>> =
+        std::sync::Mutex::new(None);
+    static ref PROXY_POOL: std::sync::Mutex<Option<std::sync::Arc<super::base::ProxyPool>>> =
+        std::sync::Mutex::new(None);
+}
+
+/// 所有通过 `new_client` 创建的客户端共用一个限速器, 第一次调用时按配置创建
+fn shared_rate_limiter(config: &GlobalConfig) -> std::sync::Arc<super::base::RateLimiter> {
+    RATE_LIMITER
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| {
+            std::sync::Arc::new(super::base::RateLimiter::new(&config.rate_limit))
+        })
+        .clone()
+}
+
+/// 所有客户端共用一个代理池, 第一次创建时启动后台健康检查, 代理列表为空时返回 None
+fn shared_proxy_pool(
+    config: &super::base::ProxyPoolConfig,
+) -> Option<std::sync::Arc<super::base::ProxyPool>> {
+    let mut shared = PROXY_POOL.lock().unwrap();
+    if shared.is_none() {
+        let pool = std::sync::Arc::new(super::base::ProxyPool::new(config.clone())?);
+        pool.clone().spawn_health_check();
+        *shared = Some(pool);
+    }
+    shared.clone()
+}
+
+/// 所有客户端共用一个账号池, 额度和失效状态在客户端之间共享
+pub fn shared_account_pool(config: &GlobalConfig) -> std::sync::Arc<super::base::AccountPool> {
+    ACCOUNT_POOL
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| {
+            std::sync::Arc::new(super::base::AccountPool::new(config.accounts.clone()))
+        })
+        .clone()
+}
+
 pub fn new_client(
     config: std::sync::Arc<GlobalConfig>,
 ) -> Result<super::base::PixivClient, super::base::PixivError> {
-    super::base::PixivClient::new_with_option(
-        super::base::PixivClientOption::new()
-            .cookie(&config.pixiv_cookie)
-            .proxy(&config.proxy)
-            .useragent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/86.0.4240.75 Safari/537.36 Edg/86.0.622.38")
-            .language("zh"),
-    )
+    let mut option = super::base::PixivClientOption::new()
+        .cookie(&config.pixiv_cookie)
+        .useragent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/86.0.4240.75 Safari/537.36 Edg/86.0.622.38")
+        .language("zh")
+        .retry(config.retry.clone())
+        .rate_limiter(shared_rate_limiter(&config));
+    if !config.accounts.is_empty() {
+        option = option.account_pool(shared_account_pool(&config));
+    }
+    match config.proxy_pool.as_ref().and_then(shared_proxy_pool) {
+        Some(pool) => option = option.proxy_pool(pool),
+        None => option = option.proxy(&config.proxy),
+    }
+    if let Some(ref host) = config.pixiv_host {
+        option = option.host(host);
+    }
+    if let Some(ref cassette) = config.cassette {
+        option = option.cassette(cassette.mode, &cassette.dir);
+    }
+    super::base::PixivClient::new_with_option(option)
 }
@@ -0,0 +1,258 @@
+use super::super::base::{Novel, PixivError};
+use super::authors_spider::load_authors;
+use super::GlobalConfig;
+use super::{AsyncQueue, PixivClient, RunnerContext, StreamWrapper, MAX_TASK_ATTEMPTS};
+use futures::{stream::select_all, StreamExt};
+use log::{error, info, warn};
+use mongodb::{bson::doc, Collection};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct UpdateNovelTask {
+    novel_id: i64,
+    is_new: bool,
+    /// 已经因为可重试的错误失败的次数
+    attempts: u32,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("")]
+    EmptyQueue,
+    #[error("抓取小说-网络错误")]
+    Pixiv(#[from] PixivError),
+    #[error("{0:?} 小说不存在或被删除")]
+    NovelNotExists(i64, bool),
+    #[error("抓取小说-稍后重试")]
+    Retry(PixivError, UpdateNovelTask),
+}
+
+async fn query_ids(
+    collection: &mut Collection,
+    filter: mongodb::bson::Document,
+    limit: usize,
+) -> Vec<i64> {
+    let cursor = collection
+        .aggregate(
+            vec![doc! {"$match" : filter}, doc! {"$limit" : limit as i64}],
+            None,
+        )
+        .await
+        .unwrap();
+    cursor
+        .filter_map(|x| async move { x.ok()?.get_i64("id").ok() })
+        .collect()
+        .await
+}
+
+async fn load_tasks(collection: &mut Collection, cache_size: usize) -> Vec<UpdateNovelTask> {
+    let mut cache: Vec<UpdateNovelTask> = query_ids(
+        collection,
+        doc! {"last_update_time" : {"$exists" : 0}},
+        cache_size,
+    )
+    .await
+    .into_iter()
+    .map(|novel_id| UpdateNovelTask {
+        novel_id,
+        is_new: true,
+        attempts: 0,
+    })
+    .collect();
+    if cache.len() < cache_size {
+        let time = chrono::Utc::now().timestamp() - 86400;
+        cache.extend(
+            query_ids(
+                collection,
+                doc! {"last_update_time" : {"$lt" : time}},
+                cache_size - cache.len(),
+            )
+            .await
+            .into_iter()
+            .map(|novel_id| UpdateNovelTask {
+                novel_id,
+                is_new: false,
+                attempts: 0,
+            }),
+        );
+    }
+    cache
+}
+
+async fn fetch_novel(
+    mut ctx: RunnerContext<UpdateNovelTask>,
+) -> (Result<(Novel, bool), Error>, RunnerContext<UpdateNovelTask>) {
+    let t = match ctx.queue.pop().await {
+        Some(x) => x,
+        None => return (Err(Error::EmptyQueue), ctx),
+    };
+    match ctx.client.load_novel(t.novel_id).await {
+        Ok(x) => (Ok((x, t.is_new)), ctx),
+        Err(PixivError::NovelNotExists(_)) => {
+            (Err(Error::NovelNotExists(t.novel_id, t.is_new)), ctx)
+        }
+        Err(e) if e.is_retryable() => (Err(Error::Retry(e, t)), ctx),
+        Err(e) => (Err(Error::Pixiv(e)), ctx),
+    }
+}
+
+async fn insert_ids(collection: &Collection, ids: &[i64]) -> usize {
+    let mut inserted_count = 0;
+    for _id in ids {
+        let mut options = mongodb::options::UpdateOptions::default();
+        options.upsert = Some(true);
+        let update_result = collection
+            .update_one(doc! {"id":_id}, doc! {"$set":{"id":_id}}, options)
+            .await
+            .unwrap();
+        if update_result.upserted_id.is_some() {
+            inserted_count += 1;
+        }
+    }
+    inserted_count
+}
+
+/// 按标签搜索和已收录插画的作者发现新小说, 只写入id, 详情由 `run` 抓取
+async fn discover(config: Arc<GlobalConfig>, mut artworks: Collection, collection: Collection) {
+    let novel_config = config.novel.as_ref().unwrap();
+    let mut client: PixivClient = super::new_client(config.clone()).unwrap();
+    loop {
+        for tag in &novel_config.tags {
+            let mut ids = Vec::new();
+            for page in 1..=novel_config.max_page {
+                match client.search_novels(tag, &novel_config.sort, page).await {
+                    Ok(x) => {
+                        let l = x.len();
+                        ids.extend(x);
+                        if l < 24 {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        break;
+                    }
+                }
+            }
+            let inserted_count = insert_ids(&collection, &ids).await;
+            info!(
+                "小说 {} 共 {} 个 , 新增了 {}",
+                tag,
+                ids.len(),
+                inserted_count
+            );
+        }
+        if novel_config.from_authors {
+            for user in load_authors(&mut artworks).await {
+                let user_id = match user.user_id {
+                    Some(x) => x,
+                    None => continue,
+                };
+                match client.load_novels_by_creator(user_id).await {
+                    Ok(ids) => {
+                        let inserted_count = insert_ids(&collection, &ids).await;
+                        if inserted_count > 0 {
+                            info!(
+                                "作者 {}-{:?} 共 {} 篇小说,新增了 {} 篇",
+                                user_id,
+                                user.name,
+                                ids.len(),
+                                inserted_count
+                            );
+                        }
+                    }
+                    Err(e) => error!("{:?}", e),
+                }
+            }
+        }
+        async_std::task::sleep(std::time::Duration::from_secs(3600)).await;
+    }
+}
+
+/// `artworks` 为插画集合, 用于按作者发现小说; 小说保存在 `collection`
+pub async fn run(config: Arc<GlobalConfig>, artworks: Collection, mut collection: Collection) {
+    let thread_num = match config.novel {
+        Some(ref x) => x.thread_num,
+        None => return,
+    };
+    async_std::task::spawn(discover(config.clone(), artworks, collection.clone()));
+    let cache = Arc::new(AsyncQueue::new());
+    let mut streams = Vec::new();
+    for _ in 0..thread_num {
+        let context = RunnerContext {
+            queue: cache.clone(),
+            client: super::new_client(config.clone()).unwrap(),
+        };
+        streams.push(StreamWrapper::new(context, fetch_novel));
+    }
+    let mut selector = select_all(streams);
+    let mut total_fill_count = 0;
+    let mut total_update_count = 0;
+    loop {
+        if cache.size().await == 0 {
+            let tasks = load_tasks(&mut collection, 1000).await;
+            if tasks.is_empty() {
+                async_std::task::sleep(std::time::Duration::from_secs(60)).await;
+                continue;
+            }
+            cache.push_all(tasks).await;
+        }
+        match selector.next().await.unwrap() {
+            Err(Error::EmptyQueue) => (),
+            Err(Error::Pixiv(PixivError::SessionExpired(x))) => {
+                error!("{} 登录已失效,小说未保存,请更新 pixiv_cookie", x)
+            }
+            Err(Error::Pixiv(x)) => error!("{:?}", x),
+            Err(Error::Retry(e, mut task)) => {
+                task.attempts += 1;
+                if task.attempts >= MAX_TASK_ATTEMPTS {
+                    warn!(
+                        "{} 已失败 {} 次,本轮不再重试: {}",
+                        task.novel_id, task.attempts, e
+                    );
+                } else {
+                    warn!("{} 重试后仍然失败,放回队列末尾: {}", task.novel_id, e);
+                    cache.push(task).await;
+                }
+            }
+            Err(Error::NovelNotExists(novel_id, is_new)) => {
+                if is_new {
+                    collection
+                        .delete_one(doc! {"id" : novel_id}, None)
+                        .await
+                        .unwrap();
+                    info!("{} 小说被删除或不存在", novel_id);
+                } else {
+                    collection
+                        .update_one(
+                            doc! {"id" : novel_id},
+                            doc! {"$set" : {"last_update_time" : chrono::Utc::now().timestamp()}},
+                            None,
+                        )
+                        .await
+                        .unwrap();
+                    info!("{} 小说被删除或不存在，无法更新", novel_id);
+                }
+            }
+            Ok((mut novel, is_new)) => {
+                novel.last_update_time = Some(chrono::Utc::now().timestamp());
+                let document = mongodb::bson::to_document(&novel).unwrap();
+                collection
+                    .update_one(doc! {"id" : novel.novel_id}, doc! {"$set" : document}, None)
+                    .await
+                    .unwrap();
+                if is_new {
+                    total_fill_count += 1;
+                    if total_fill_count % 100 == 0 {
+                        info!("新增了 {} 篇小说", total_fill_count);
+                    }
+                } else {
+                    total_update_count += 1;
+                    if total_update_count % 100 == 0 {
+                        info!("更新了 {} 篇小说", total_update_count);
+                    }
+                }
+            }
+        };
+    }
+}
@@ -0,0 +1,19 @@
+use super::super::base::{dhash, hash_to_hex};
+use super::GlobalConfig;
+use mongodb::{bson::Bson, Collection};
+use std::sync::Arc;
+
+/// 为已下载的原图计算感知哈希, 写入 `files.<页>.phash`, 用于查找重复上传的作品
+pub async fn run(config: Arc<GlobalConfig>, collection: Collection) {
+    if config.download.is_none() {
+        return;
+    }
+    super::file_tasks::run(
+        collection,
+        "phash",
+        "phash_failed",
+        "计算感知哈希",
+        |source, _, _| dhash(&source).map(|x| Bson::String(hash_to_hex(x))),
+    )
+    .await
+}
@@ -0,0 +1,184 @@
+use super::{GlobalConfig, PixivClient};
+use log::{error, info};
+use mongodb::bson::doc;
+use mongodb::Collection;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+#[derive(serde::Deserialize)]
+struct RankingConfig {
+    mode: String,
+    content: String,
+    max_page: u32,
+    /// 回溯历史榜单的起始日期, 格式为 `20201201`, 不设置则只抓取最新一期
+    #[serde(default)]
+    date_from: Option<String>,
+    /// 回溯的结束日期, 不设置则到最新一期的前一天
+    #[serde(default)]
+    date_to: Option<String>,
+}
+
+const DATE_FORMAT: &str = "%Y%m%d";
+
+/// 需要回溯的日期 (含首尾); 没有设置 `to` 时回溯到 `latest` 的前一天
+fn history_dates(from: &str, to: Option<&str>, latest: Option<&str>) -> Vec<String> {
+    let parse = |x: &str| chrono::NaiveDate::parse_from_str(x, DATE_FORMAT);
+    let from = match parse(from) {
+        Ok(x) => x,
+        Err(_) => {
+            error!("排行榜日期格式错误: {}", from);
+            return Vec::new();
+        }
+    };
+    let to = match (to, latest) {
+        (Some(x), _) => match parse(x) {
+            Ok(x) => x,
+            Err(_) => {
+                error!("排行榜日期格式错误: {}", x);
+                return Vec::new();
+            }
+        },
+        (None, Some(x)) => match parse(x) {
+            Ok(x) => x - chrono::Duration::days(1),
+            Err(_) => return Vec::new(),
+        },
+        (None, None) => return Vec::new(),
+    };
+    let mut dates = Vec::new();
+    let mut date = from;
+    while date <= to {
+        dates.push(date.format(DATE_FORMAT).to_string());
+        date += chrono::Duration::days(1);
+    }
+    dates
+}
+
+/// 抓取一期榜单, `date` 为 None 时抓取最新一期; 成功时返回榜单日期
+async fn crawl_ranking(
+    ranking_config: &RankingConfig,
+    date: Option<&str>,
+    client: &mut PixivClient,
+    collection: &Collection,
+) -> Option<String> {
+    let mut page_num = 1;
+    let mut total_count: usize = 0;
+    let mut inserted_count: usize = 0;
+    let mut ranking_date = None;
+    while page_num <= ranking_config.max_page {
+        let page = match client
+            .ranking(
+                &ranking_config.mode,
+                &ranking_config.content,
+                date,
+                page_num,
+            )
+            .await
+        {
+            Ok(x) => x,
+            Err(e) => {
+                error!("{:?}", e);
+                break;
+            }
+        };
+        for item in &page.items {
+            let mut options = mongodb::options::UpdateOptions::default();
+            options.upsert = Some(true);
+            let ranking = doc! {
+                "mode" : &page.mode,
+                "content" : &page.content,
+                "date" : &page.date,
+                "rank" : item.rank,
+            };
+            let update_result = collection
+                .update_one(
+                    doc! {"id" : item.artwork_id},
+                    doc! {"$set" : {"id" : item.artwork_id}, "$addToSet" : {"rankings" : ranking}},
+                    options,
+                )
+                .await
+                .unwrap();
+            if update_result.upserted_id.is_some() {
+                inserted_count += 1;
+            }
+        }
+        total_count += page.items.len();
+        ranking_date = Some(page.date);
+        match page.next {
+            Some(_) => page_num += 1,
+            None => break,
+        }
+    }
+    info!(
+        "排行榜 {}-{} ({:?}) 共 {} 个作品 , 新增了 {}",
+        ranking_config.mode, ranking_config.content, ranking_date, total_count, inserted_count
+    );
+    ranking_date
+}
+
+pub async fn run(config: Arc<GlobalConfig>, collection: Collection) {
+    let path = match config.ranking_config_path {
+        Some(ref x) => x.clone(),
+        None => return,
+    };
+    let mut client = super::new_client(config.clone()).unwrap();
+    // 历史榜单不会再变化, 抓取成功后不再重复抓取
+    let mut finished: HashSet<(String, String, String)> = HashSet::new();
+    loop {
+        let rankings_config = match serde_json::from_str::<Vec<RankingConfig>>(
+            &std::fs::read_to_string(&path).unwrap(),
+        ) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("{:?}", e);
+                async_std::task::sleep(std::time::Duration::from_secs(10)).await;
+                continue;
+            }
+        };
+        for ranking_config in &rankings_config {
+            let latest = crawl_ranking(ranking_config, None, &mut client, &collection).await;
+            let from = match ranking_config.date_from {
+                Some(ref x) => x,
+                None => continue,
+            };
+            let to = ranking_config.date_to.as_deref();
+            for date in history_dates(from, to, latest.as_deref()) {
+                let key = (
+                    ranking_config.mode.clone(),
+                    ranking_config.content.clone(),
+                    date,
+                );
+                if finished.contains(&key) {
+                    continue;
+                }
+                if crawl_ranking(ranking_config, Some(&key.2), &mut client, &collection)
+                    .await
+                    .is_some()
+                {
+                    finished.insert(key);
+                }
+            }
+        }
+        // 排行榜每天更新一次, 不需要频繁抓取
+        async_std::task::sleep(std::time::Duration::from_secs(3600)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_dates_between() {
+        assert_eq!(
+            history_dates("20201230", Some("20210102"), None),
+            vec!["20201230", "20201231", "20210101", "20210102"]
+        );
+        assert_eq!(
+            history_dates("20200227", None, Some("20200301")),
+            vec!["20200227", "20200228", "20200229"]
+        );
+        assert!(history_dates("20200301", None, None).is_empty());
+        assert!(history_dates("2020-03-01", Some("20200302"), None).is_empty());
+        assert!(history_dates("20200302", Some("20200301"), None).is_empty());
+    }
+}
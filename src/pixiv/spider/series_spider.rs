@@ -0,0 +1,194 @@
+use super::super::base::{PixivError, PixivSeries, PixivSeriesItem};
+use super::{GlobalConfig, PixivClient};
+use futures::StreamExt;
+use log::{error, info};
+use mongodb::{bson::doc, Collection};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// 已收录作品所属的系列中, 从未抓取或超过 `refresh_secs` 没有更新的系列
+///
+/// 抓取失败的系列记录了 `failed_time`, 同样等到下一次刷新
+async fn load_series_ids(
+    artworks: &Collection,
+    collection: &Collection,
+    refresh_secs: i64,
+) -> Vec<i64> {
+    let cursor = artworks
+        .aggregate(
+            vec![
+                doc! {"$match" : {"series.id" : {"$exists" : 1}}},
+                doc! {"$group" : {"_id" : "$series.id"}},
+            ],
+            None,
+        )
+        .await
+        .unwrap();
+    let ids: Vec<i64> = cursor
+        .filter_map(|x| async move { x.ok()?.get_i64("_id").ok() })
+        .collect()
+        .await;
+    let time = chrono::Utc::now().timestamp() - refresh_secs;
+    let cursor = collection
+        .find(
+            doc! {
+                "id" : {"$in" : ids.clone()},
+                "$or" : [
+                    {"last_update_time" : {"$gte" : time}},
+                    {"failed_time" : {"$gte" : time}}
+                ],
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    let fresh: HashSet<i64> = cursor
+        .filter_map(|x| async move { x.ok()?.get_i64("id").ok() })
+        .collect()
+        .await;
+    ids.into_iter().filter(|x| !fresh.contains(x)).collect()
+}
+
+/// 逐页读取系列, 直到取得全部话数
+async fn fetch_series(client: &mut PixivClient, series_id: i64) -> Result<PixivSeries, PixivError> {
+    let mut series = client.load_series(series_id, 1).await?;
+    let mut page = 1;
+    loop {
+        let total = series.total.unwrap_or(0) as usize;
+        if series.items.len() >= total {
+            break;
+        }
+        page += 1;
+        let next = client.load_series(series_id, page).await?;
+        if next.items.is_empty() {
+            break;
+        }
+        series.items.extend(next.items);
+    }
+    series.items.sort_by_key(|x| x.order);
+    series.items.dedup_by_key(|x| x.artwork_id);
+    Ok(series)
+}
+
+async fn save_series(
+    artworks: &Collection,
+    collection: &Collection,
+    mut series: PixivSeries,
+) -> usize {
+    let old_ids: HashSet<i64> = match collection
+        .find_one(doc! {"id" : series.series_id}, None)
+        .await
+        .unwrap()
+    {
+        Some(x) => x
+            .get_array("items")
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|x| x.as_document()?.get_i64("id").ok())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        None => HashSet::new(),
+    };
+    let is_new = old_ids.is_empty();
+    let new_items: Vec<&PixivSeriesItem> = series
+        .items
+        .iter()
+        .filter(|x| !old_ids.contains(&x.artwork_id))
+        .collect();
+    let now = chrono::Utc::now().timestamp();
+    // 新增的话放入作品集合, 由 artworks_spider 抓取详情
+    for item in &new_items {
+        let mut options = mongodb::options::UpdateOptions::default();
+        options.upsert = Some(true);
+        artworks
+            .update_one(
+                doc! {"id" : item.artwork_id},
+                doc! {"$set" : {"id" : item.artwork_id}},
+                options,
+            )
+            .await
+            .unwrap();
+    }
+    let new_count = new_items.len();
+    if !is_new && new_count > 0 {
+        info!(
+            "系列 {}-{:?} 新增了 {} 话",
+            series.series_id, series.title, new_count
+        );
+    }
+    series.last_update_time = Some(now);
+    let mut document = mongodb::bson::to_document(&series).unwrap();
+    // 记录最近一次发现新话的时间, 方便读者查看更新
+    if !is_new && new_count > 0 {
+        document.insert("last_new_time", now);
+    }
+    let mut options = mongodb::options::UpdateOptions::default();
+    options.upsert = Some(true);
+    collection
+        .update_one(
+            doc! {"id" : series.series_id},
+            doc! {"$set" : document},
+            options,
+        )
+        .await
+        .unwrap();
+    new_count
+}
+
+/// 抓取失败的系列记录失败时间, 避免每一轮都重新请求
+async fn mark_failed(collection: &Collection, series_id: i64) {
+    let mut options = mongodb::options::UpdateOptions::default();
+    options.upsert = Some(true);
+    collection
+        .update_one(
+            doc! {"id" : series_id},
+            doc! {"$set" : {"failed_time" : chrono::Utc::now().timestamp()}},
+            options,
+        )
+        .await
+        .unwrap();
+}
+
+/// 抓取已收录作品所属的系列, 保存到 `collection`; `artworks` 为作品集合
+pub async fn run(config: Arc<GlobalConfig>, artworks: Collection, collection: Collection) {
+    let refresh_secs = match config.series {
+        Some(ref x) => x.refresh_hours as i64 * 3600,
+        None => return,
+    };
+    let mut client = super::new_client(config.clone()).unwrap();
+    loop {
+        let ids = load_series_ids(&artworks, &collection, refresh_secs).await;
+        let mut total_count = 0;
+        for series_id in ids {
+            match fetch_series(&mut client, series_id).await {
+                Ok(series) => {
+                    save_series(&artworks, &collection, series).await;
+                    total_count += 1;
+                    if total_count % 100 == 0 {
+                        info!("更新了 {} 个系列", total_count);
+                    }
+                }
+                Err(PixivError::SeriesNotExists(_)) => {
+                    info!("系列 {} 不存在或被删除", series_id);
+                    let mut options = mongodb::options::UpdateOptions::default();
+                    options.upsert = Some(true);
+                    collection
+                        .update_one(
+                            doc! {"id" : series_id},
+                            doc! {"$set" : {"last_update_time" : chrono::Utc::now().timestamp()}},
+                            options,
+                        )
+                        .await
+                        .unwrap();
+                }
+                Err(e) => {
+                    error!("系列 {} 抓取失败: {:?}", series_id, e);
+                    mark_failed(&collection, series_id).await;
+                }
+            }
+        }
+        async_std::task::sleep(std::time::Duration::from_secs(3600)).await;
+    }
+}
@@ -0,0 +1,33 @@
+use super::super::base::{generate_thumbnails, ThumbnailError};
+use super::GlobalConfig;
+use mongodb::{bson::Bson, Collection};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// 为已下载的原图生成缩略图并记录到作品的 `files.thumbnails`
+pub async fn run(config: Arc<GlobalConfig>, collection: Collection) {
+    let thumbnail_config = match config.thumbnail {
+        Some(ref x) => x,
+        None => return,
+    };
+    let dir = thumbnail_config.dir.as_ref().map(PathBuf::from);
+    let sizes = thumbnail_config.sizes.clone();
+    let format = thumbnail_config.format;
+    super::file_tasks::run(
+        collection,
+        "thumbnails",
+        "thumbnail_failed",
+        "生成缩略图",
+        move |source, artwork_id, page| {
+            let thumbnails =
+                generate_thumbnails(&source, dir.as_deref(), artwork_id, page, &sizes, format)?;
+            Ok::<_, ThumbnailError>(Bson::Array(
+                thumbnails
+                    .iter()
+                    .map(|x| Bson::Document(mongodb::bson::to_document(x).unwrap()))
+                    .collect(),
+            ))
+        },
+    )
+    .await
+}
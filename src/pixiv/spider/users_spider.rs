@@ -0,0 +1,164 @@
+use super::super::base::{PixivError, PixivUserProfile};
+use super::{GlobalConfig, PixivClient};
+use futures::StreamExt;
+use log::{error, info};
+use mongodb::{bson::doc, Collection};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// 作品集合中出现过但还没有资料的作者, 以及资料超过 `refresh_secs` 没有更新的作者
+///
+/// following_spider 写入的作者还没有资料, 排在最前面;
+/// 抓取失败的作者记录了 `failed_time`, 等到下一次刷新
+async fn load_user_ids(
+    artworks: &Collection,
+    collection: &Collection,
+    refresh_secs: i64,
+) -> Vec<i64> {
+    let cursor = artworks
+        .aggregate(
+            vec![
+                doc! {"$match" : {"user.id" : {"$exists" : 1}}},
+                doc! {"$group" : {"_id" : "$user.id"}},
+            ],
+            None,
+        )
+        .await
+        .unwrap();
+    let mut ids: Vec<i64> = collection
+        .find(doc! {"last_update_time" : {"$exists" : 0}}, None)
+        .await
+        .unwrap()
+        .filter_map(|x| async move { x.ok()?.get_i64("id").ok() })
+        .collect()
+        .await;
+    let seeded: HashSet<i64> = ids.iter().cloned().collect();
+    let from_artworks: Vec<i64> = cursor
+        .filter_map(|x| async move { x.ok()?.get_i64("_id").ok() })
+        .collect()
+        .await;
+    ids.extend(from_artworks.into_iter().filter(|x| !seeded.contains(x)));
+    let time = chrono::Utc::now().timestamp() - refresh_secs;
+    let cursor = collection
+        .find(
+            doc! {"$or" : [
+                {"last_update_time" : {"$gte" : time}},
+                {"failed_time" : {"$gte" : time}}
+            ]},
+            None,
+        )
+        .await
+        .unwrap();
+    let fresh: HashSet<i64> = cursor
+        .filter_map(|x| async move { x.ok()?.get_i64("id").ok() })
+        .collect()
+        .await;
+    ids.into_iter().filter(|x| !fresh.contains(x)).collect()
+}
+
+/// 保存资料; 改名时在 `name_history` 中追加新名字, 账号状态变化时记录 `status_time`
+async fn save_profile(collection: &Collection, mut profile: PixivUserProfile) {
+    let now = chrono::Utc::now().timestamp();
+    let old = collection
+        .find_one(doc! {"id" : profile.user_id}, None)
+        .await
+        .unwrap();
+    let old_name = old
+        .as_ref()
+        .and_then(|x| x.get_str("name").ok())
+        .map(|x| x.to_string());
+    let old_status = old
+        .as_ref()
+        .and_then(|x| x.get_str("status").ok())
+        .map(|x| x.to_string());
+    profile.last_update_time = Some(now);
+    let mut document = mongodb::bson::to_document(&profile).unwrap();
+    document.insert("status", "active");
+    if old_status.as_deref() != Some("active") {
+        document.insert("status_time", now);
+    }
+    let mut update = doc! {"$set" : document};
+    if profile.name.is_some() && profile.name != old_name {
+        if let Some(ref old_name) = old_name {
+            info!(
+                "作者 {} 改名: {} -> {:?}",
+                profile.user_id, old_name, profile.name
+            );
+        }
+        update.insert(
+            "$push",
+            doc! {"name_history" : {"name" : profile.name.clone().unwrap(), "time" : now}},
+        );
+    }
+    let mut options = mongodb::options::UpdateOptions::default();
+    options.upsert = Some(true);
+    collection
+        .update_one(doc! {"id" : profile.user_id}, update, options)
+        .await
+        .unwrap();
+}
+
+/// 作者退会或被封禁后资料无法访问, 保留旧资料并标记状态
+async fn mark_deleted(collection: &Collection, user_id: i64) {
+    let now = chrono::Utc::now().timestamp();
+    let old_status = collection
+        .find_one(doc! {"id" : user_id}, None)
+        .await
+        .unwrap()
+        .and_then(|x| x.get_str("status").ok().map(|x| x.to_string()));
+    let mut document = doc! {"id" : user_id, "status" : "deleted", "last_update_time" : now};
+    if old_status.as_deref() != Some("deleted") {
+        info!("作者 {} 已退会或不存在", user_id);
+        document.insert("status_time", now);
+    }
+    let mut options = mongodb::options::UpdateOptions::default();
+    options.upsert = Some(true);
+    collection
+        .update_one(doc! {"id" : user_id}, doc! {"$set" : document}, options)
+        .await
+        .unwrap();
+}
+
+/// 抓取失败的作者记录失败时间, 避免每一轮都重新请求
+async fn mark_failed(collection: &Collection, user_id: i64) {
+    let mut options = mongodb::options::UpdateOptions::default();
+    options.upsert = Some(true);
+    collection
+        .update_one(
+            doc! {"id" : user_id},
+            doc! {"$set" : {"failed_time" : chrono::Utc::now().timestamp()}},
+            options,
+        )
+        .await
+        .unwrap();
+}
+
+/// 定时抓取作者资料保存到 `collection`, 作者来自作品集合 `artworks`
+pub async fn run(config: Arc<GlobalConfig>, artworks: Collection, collection: Collection) {
+    let refresh_secs = match config.users {
+        Some(ref x) => x.refresh_hours as i64 * 3600,
+        None => return,
+    };
+    let mut client: PixivClient = super::new_client(config.clone()).unwrap();
+    loop {
+        let ids = load_user_ids(&artworks, &collection, refresh_secs).await;
+        let mut total_count = 0;
+        for user_id in ids {
+            match client.load_user(user_id).await {
+                Ok(profile) => {
+                    save_profile(&collection, profile).await;
+                    total_count += 1;
+                    if total_count % 100 == 0 {
+                        info!("更新了 {} 个作者资料", total_count);
+                    }
+                }
+                Err(PixivError::UserNotExists(_)) => mark_deleted(&collection, user_id).await,
+                Err(e) => {
+                    error!("作者 {} 资料抓取失败: {:?}", user_id, e);
+                    mark_failed(&collection, user_id).await;
+                }
+            }
+        }
+        async_std::task::sleep(std::time::Duration::from_secs(3600)).await;
+    }
+}
@@ -1,3 +1,275 @@
+//! 本地的 pixiv.net 替身, 用于在不访问网络的情况下端到端测试 `spider_run`
+//!
+//! 用法: `test [监听地址] [种子]`, 默认 `127.0.0.1:8080 42`,
+//! 然后在 config.json 中设置 `"pixiv_host": "http://127.0.0.1:8080"`。
+//!
+//! 固定的异常数据:
+//! - 作品 ID 能被 17 整除时作品页返回 404
+//! - 作品 ID 能被 23 整除时作品页第一次请求返回 429
+//! - 作品 ID 能被 29 整除时作品页的 preload-data 被截断
+//! - 搜索标签 `throttled` 总是返回 429, `malformed` 返回非法 JSON
+//! - 不存在的作者返回 404
+use async_std::sync::Mutex;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+const PAGE_SIZE: usize = 60;
+const TAGS: [&str; 8] = [
+    "オリジナル",
+    "風景",
+    "女の子",
+    "東方",
+    "初音ミク",
+    "落書き",
+    "漫画",
+    "創作",
+];
+
+struct MockArtwork {
+    id: i64,
+    user_id: i64,
+    illust_type: i64,
+    tags: Vec<&'static str>,
+    bookmark_count: i64,
+    view_count: i64,
+}
+
+struct Fixtures {
+    artworks: BTreeMap<i64, MockArtwork>,
+    users: BTreeMap<i64, Vec<i64>>,
+    throttled_once: Mutex<HashSet<i64>>,
+}
+
+/// 线性同余生成器, 保证同一个种子生成同一份数据
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+    fn range(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+impl Fixtures {
+    fn seeded(seed: u64) -> Fixtures {
+        let mut rng = Lcg(seed);
+        let mut artworks = BTreeMap::new();
+        let mut users = BTreeMap::new();
+        let mut next_id: i64 = 80000000;
+        for u in 0..20 {
+            let user_id = 1000 + u;
+            let mut ids = Vec::new();
+            for _ in 0..(1 + rng.range(15)) {
+                next_id += 1 + rng.range(7) as i64;
+                let mut tags = Vec::new();
+                for _ in 0..(1 + rng.range(3)) {
+                    let tag = TAGS[rng.range(TAGS.len() as u64) as usize];
+                    if !tags.contains(&tag) {
+                        tags.push(tag);
+                    }
+                }
+                artworks.insert(
+                    next_id,
+                    MockArtwork {
+                        id: next_id,
+                        user_id,
+                        illust_type: if rng.range(4) == 0 { 1 } else { 0 },
+                        tags,
+                        bookmark_count: rng.range(5000) as i64,
+                        view_count: rng.range(100000) as i64,
+                    },
+                );
+                ids.push(next_id);
+            }
+            users.insert(user_id, ids);
+        }
+        Fixtures {
+            artworks,
+            users,
+            throttled_once: Mutex::new(HashSet::new()),
+        }
+    }
+}
+
+type Request = tide::Request<Arc<Fixtures>>;
+
+fn artwork_json(artwork: &MockArtwork) -> serde_json::Value {
+    let tags: Vec<serde_json::Value> = artwork
+        .tags
+        .iter()
+        .map(|t| serde_json::json!({"tag": t, "translation": {"en": format!("{}-en", t)}}))
+        .collect();
+    serde_json::json!({
+        "id": artwork.id.to_string(),
+        "illustId": artwork.id.to_string(),
+        "title": format!("mock artwork {}", artwork.id),
+        "illustType": artwork.illust_type,
+        "illustComment": format!("caption of {}", artwork.id),
+        "createDate": "2020-12-01T00:00:00+00:00",
+        "sl": 2,
+        "width": 1200,
+        "height": 1600,
+        "pageCount": 1,
+        "bookmarkCount": artwork.bookmark_count,
+        "viewCount": artwork.view_count,
+        "userId": artwork.user_id.to_string(),
+        "userName": format!("user{}", artwork.user_id),
+        "userAccount": format!("account{}", artwork.user_id),
+        "urls": {
+            "thumb": format!("https://i.pximg.net/c/250x250/img-master/img/{}_p0_square1200.jpg", artwork.id),
+            "regular": format!("https://i.pximg.net/img-master/img/{}_p0_master1200.jpg", artwork.id),
+            "original": format!("https://i.pximg.net/img-original/img/{}_p0.png", artwork.id),
+        },
+        "tags": {"tags": tags},
+    })
+}
+
+fn status(code: u16) -> tide::Response {
+    tide::Response::new(code)
+}
+
+fn json_response(body: String) -> tide::Response {
+    tide::Response::builder(200)
+        .body(body)
+        .content_type(tide::http::mime::JSON)
+        .build()
+}
+
+async fn artwork_page(req: Request) -> tide::Result {
+    let id = match req.param("id")?.parse::<i64>() {
+        Ok(x) => x,
+        Err(_) => return Ok(status(404)),
+    };
+    let fixtures = req.state();
+    let artwork = match fixtures.artworks.get(&id) {
+        Some(x) if id % 17 != 0 => x,
+        _ => return Ok(status(404)),
+    };
+    if id % 23 == 0 && fixtures.throttled_once.lock().await.insert(id) {
+        return Ok(tide::Response::builder(429).header("Retry-After", "1").build());
+    }
+    let mut illust = serde_json::Map::new();
+    illust.insert(id.to_string(), artwork_json(artwork));
+    let mut preload = serde_json::json!({ "illust": illust }).to_string();
+    if id % 29 == 0 {
+        preload.truncate(preload.len() / 2);
+    }
+    let global = serde_json::json!({"token": "mock", "userData": {"id": "1", "name": "mock"}});
+    let html = format!(
+        "<!DOCTYPE html><html><head><meta name=\"global-data\" id=\"meta-global-data\" content='{}'><meta name=\"preload-data\" id=\"meta-preload-data\" content='{}'></head><body></body></html>",
+        global, preload
+    );
+    Ok(tide::Response::builder(200)
+        .body(html)
+        .content_type(tide::http::mime::HTML)
+        .build())
+}
+
+async fn user_profile_all(req: Request) -> tide::Result {
+    let id = match req.param("id")?.parse::<i64>() {
+        Ok(x) => x,
+        Err(_) => return Ok(status(404)),
+    };
+    let fixtures = req.state();
+    let ids = match fixtures.users.get(&id) {
+        Some(x) => x,
+        None => {
+            return Ok(tide::Response::builder(404)
+                .body(r#"{"error":true,"message":"User not found","body":[]}"#)
+                .content_type(tide::http::mime::JSON)
+                .build())
+        }
+    };
+    let mut illusts = serde_json::Map::new();
+    let mut manga = serde_json::Map::new();
+    for artwork_id in ids {
+        let artwork = &fixtures.artworks[artwork_id];
+        if artwork.illust_type == 1 {
+            manga.insert(artwork_id.to_string(), serde_json::Value::Null);
+        } else {
+            illusts.insert(artwork_id.to_string(), serde_json::Value::Null);
+        }
+    }
+    Ok(json_response(
+        serde_json::json!({"error": false, "message": "", "body": {"illusts": illusts, "manga": manga}})
+            .to_string(),
+    ))
+}
+
+fn query_value(req: &Request, key: &str) -> Option<String> {
+    req.url()
+        .query_pairs()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.to_string())
+}
+
+async fn search(req: Request, illust_type: i64, json_key: &str) -> tide::Result {
+    let tag = urlencoding::decode(req.param("tag")?)
+        .map(|x| x.to_string())
+        .unwrap_or_default();
+    match tag.as_str() {
+        "throttled" => return Ok(status(429)),
+        "malformed" => return Ok(json_response(r#"{"error":false,"body":{"#.to_string())),
+        _ => (),
+    }
+    let page = query_value(&req, "p")
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or(1)
+        .max(1);
+    let matched: Vec<&MockArtwork> = req
+        .state()
+        .artworks
+        .values()
+        .filter(|x| x.illust_type == illust_type && x.tags.iter().any(|t| *t == tag))
+        .collect();
+    let data: Vec<serde_json::Value> = matched
+        .iter()
+        .skip((page - 1) * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|x| serde_json::json!({"id": x.id.to_string(), "userId": x.user_id.to_string()}))
+        .collect();
+    let mut body = serde_json::Map::new();
+    body.insert(
+        json_key.to_string(),
+        serde_json::json!({"data": data, "total": matched.len()}),
+    );
+    Ok(json_response(
+        serde_json::json!({"error": false, "body": body}).to_string(),
+    ))
+}
+
 fn main() {
-    
-}
\ No newline at end of file
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let addr = args
+        .get(0)
+        .cloned()
+        .unwrap_or("127.0.0.1:8080".to_string());
+    let seed = args
+        .get(1)
+        .and_then(|x| x.parse::<u64>().ok())
+        .unwrap_or(42);
+    let fixtures = Arc::new(Fixtures::seeded(seed));
+    println!(
+        "mock pixiv 监听 {} , 种子 {} , {} 个作者 , {} 个作品",
+        addr,
+        seed,
+        fixtures.users.len(),
+        fixtures.artworks.len()
+    );
+    println!("可搜索的标签: {}", TAGS.join(" "));
+
+    let mut app = tide::with_state(fixtures);
+    app.at("/artworks/:id").get(artwork_page);
+    app.at("/ajax/user/:id/profile/all").get(user_profile_all);
+    app.at("/ajax/search/illustrations/:tag")
+        .get(|req: Request| async move { search(req, 0, "illust").await });
+    app.at("/ajax/search/manga/:tag")
+        .get(|req: Request| async move { search(req, 1, "manga").await });
+    async_std::task::block_on(app.listen(addr)).unwrap();
+}